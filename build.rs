@@ -1,15 +1,23 @@
-// To build, download the libsais library (libsais.c and libsais.h from https://github.com/IlyaGrebnov/libsais) into the libs folder
+// To build, download the libsais library (libsais.c, libsais.h, libsais64.c and libsais64.h from https://github.com/IlyaGrebnov/libsais) into the libs folder
 
 // The libsais library is released under Apache License 2.0 and is not modified for the purposes of this project
 // Copyright of the libsais library (c) 2021 Ilya Grebnov <ilya.grebnov@gmail.com>
 
 // Uses libsais v2.70
+// Without the libsais feature (e.g. --no-default-features --features pure-rust) no C sources are needed
 
+#[cfg(not(feature = "libsais"))]
+fn main() {}
+
+#[cfg(feature = "libsais")]
 fn main() {
     cc::Build::new()
         .flag("-Wno-unused-parameter")
         .file("libs/libsais.c")
+        .file("libs/libsais64.c")
         .compile("libsais");
     println!("cargo:rerun-if-changed=libs/libsais.c");
     println!("cargo:rerun-if-changed=libs/libsais.h");
+    println!("cargo:rerun-if-changed=libs/libsais64.c");
+    println!("cargo:rerun-if-changed=libs/libsais64.h");
 }
@@ -0,0 +1,79 @@
+use crate::documents;
+use crate::incan74re::DictIndex;
+use crate::index::SaIndex;
+
+// Alignment of words in binary structured data (arrays of fixed-size records, sensor frames)
+// An occurrence counts only at a start congruent to offset mod stride (within its document), a length only
+// as a multiple of unit, so a dictionary for 16-byte records (stride and unit 16) lines up with the records
+// Strides and units of 0 or 1 leave words unaligned
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Alignment {
+    pub stride: usize,
+    pub offset: usize,
+    pub unit:   usize
+}
+
+impl Default for Alignment {
+    fn default() -> Self { Self { stride: 1, offset: 0, unit: 1 } }
+}
+
+impl Alignment {
+    // Records of size bytes, words start with a record and hold whole records
+    pub fn records(size: usize) -> Self { Self { stride: size, offset: 0, unit: size } }
+
+    pub fn is_active(&self) -> bool { self.stride > 1 || self.unit > 1 }
+
+    pub fn fits_len(&self, len: usize) -> bool { len.is_multiple_of(self.unit.max(1)) }
+}
+
+// Whether an occurrence may start at the position, relative to the start of its document
+pub fn starts<I: SaIndex>(dict_index: &DictIndex<I>, pos: usize) -> bool {
+    let stride = dict_index.alignment.stride;
+    if stride <= 1 { return true; }
+
+    let doc_starts = &dict_index.doc_starts;
+    let start = if doc_starts.len() > 1 { doc_starts[documents::document(doc_starts, pos)] } else { 0 };
+    (pos - start) % stride == dict_index.alignment.offset % stride
+}
+
+// Whether the occurrence of a word of len bytes at loc is aligned
+pub fn allows<I: SaIndex>(dict_index: &DictIndex<I>, loc: usize, len: usize) -> bool {
+    !dict_index.alignment.is_active() || (dict_index.alignment.fits_len(len) && starts(dict_index, loc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+
+    // 16-byte records: a cycling counter, one of a few kinds and one of a few payloads
+    fn frames() -> Vec<u8> {
+        let kinds = [*b"TEMP", *b"PRES", *b"HUMI"];
+        let payloads = [*b"\x00\x01\x02\x03\x00\x01\x02\x03", *b"\x10\x00\x00\x00\x10\x00\x00\x00", *b"abcdabcd", *b"\xff\xfe\xff\xfe\x00\x00\x01\x00"];
+        (0..600u32).flat_map(|i| {
+            let mut frame = (i % 10).to_be_bytes().to_vec();
+            frame.extend(kinds[(i % 3) as usize]);
+            frame.extend(payloads[(i * 5 / 7 % 4) as usize]);
+            frame
+        }).collect()
+    }
+
+    #[test]
+    fn words_are_aligned() {
+        let buf = frames();
+        for alignment in [Alignment::records(16), Alignment { stride: 8, offset: 4, unit: 4 }, Alignment { stride: 4, offset: 1, unit: 1 }] {
+            let encoded = crate::encode(&buf, Options { alignment, ..Default::default() }).unwrap();
+            assert!(!encoded.dictionary.is_empty(), "{alignment:?}");
+
+            let mut pos = 0;
+            for &token in &encoded.tokens {
+                let len = if token < 256 { 1 } else { encoded.dictionary.words()[token as usize - 256].len() };
+                if token >= 256 {
+                    assert_eq!(pos % alignment.stride, alignment.offset, "{alignment:?}: word {} at {pos}", token - 256);
+                    assert_eq!(len % alignment.unit, 0, "{alignment:?}: word {} of {len} bytes", token - 256);
+                }
+                pos += len;
+            }
+        }
+    }
+}
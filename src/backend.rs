@@ -0,0 +1,61 @@
+use crate::error::Result;
+use crate::index::SaIndex;
+
+#[cfg(not(any(feature = "libsais", feature = "pure-rust")))]
+compile_error!("Enable at least one SA backend: the libsais or the pure-rust feature");
+
+// Builds the suffix array and the LCP array used by initialize and the match finder
+// The LCP array has lcp[i] = lcp(SA[i], SA[i+1]) and ends with a 0 (the sentinel is assumed at the end)
+pub trait Backend {
+    fn suffix_array<I: SaIndex>(buf: &[u8]) -> Result<Vec<I>>;
+    fn lcp_array<I: SaIndex>(buf: &[u8], sa: &[I]) -> Result<Vec<I>>;
+    // The suffix array of a text over the alphabet [0, k), used for several documents (see documents.rs)
+    // The text may be overwritten
+    fn suffix_array_int<I: SaIndex>(text: &mut [I], k: usize) -> Result<Vec<I>>;
+}
+
+// libsais is the fast default, the pure rust backend is used when the crate is built without it
+#[cfg(feature = "libsais")]
+pub type DefaultBackend = Libsais;
+#[cfg(all(feature = "pure-rust", not(feature = "libsais")))]
+pub type DefaultBackend = crate::sais::PureRust;
+
+#[cfg(feature = "libsais")]
+pub struct Libsais;
+
+#[cfg(feature = "libsais")]
+impl Backend for Libsais {
+    fn suffix_array<I: SaIndex>(buf: &[u8]) -> Result<Vec<I>> {
+        let mut sa = vec![I::ZERO; buf.len()];
+        I::libsais(buf, &mut sa)?;
+        assert!(sa.len() == buf.len());
+
+        Ok(sa)
+    }
+
+    fn suffix_array_int<I: SaIndex>(text: &mut [I], k: usize) -> Result<Vec<I>> {
+        let mut sa = vec![I::ZERO; text.len()];
+        I::libsais_int(text, &mut sa, k)?;
+
+        Ok(sa)
+    }
+
+    fn lcp_array<I: SaIndex>(buf: &[u8], sa: &[I]) -> Result<Vec<I>> {
+        let mut plcp = vec![I::ZERO; buf.len()];
+        let mut lcp = vec![I::ZERO; buf.len()+1];
+
+        I::libsais_plcp(buf, sa, &mut plcp)?;
+        assert!(plcp.len() == buf.len());
+
+        I::libsais_lcp(&plcp, sa, &mut lcp)?;
+        // This is a bit of hacky magic because the previous implementation of an LCP array (using kasai's alg)
+        // produced an array ending with 0, while the libsais version has the extra 0 in the beginning
+        // This is ultimately based on where you assume the sentinel token to be placed
+        // As it is purely an implementation choice, I found this to be easier to adapt,
+        // rather than rewriting the matchfinder
+        lcp.remove(0);
+        assert!(lcp.len() == buf.len());
+
+        Ok(lcp)
+    }
+}
@@ -0,0 +1,53 @@
+use crate::documents;
+use crate::incan74re::DictIndex;
+use crate::index::SaIndex;
+
+// Where words may start and end, for text where words cutting through natural words ("e th", "ing t") are unwanted
+// Every occurrence of a word must start and end at a boundary, the match finder skips matches that can't,
+// counting and split skip the occurrences that don't
+// The start and end of every document are always boundaries, with no class enabled every position is one
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Boundaries {
+    pub whitespace:  bool, // Between a whitespace and a non-whitespace byte
+    pub punctuation: bool, // Between an ASCII punctuation and a non-punctuation byte
+    pub utf8:        bool  // Never before a UTF-8 continuation byte (combined with the others, they must hold as well)
+}
+
+impl Boundaries {
+    // All of the classes, words of natural language text
+    pub fn words() -> Self { Self { whitespace: true, punctuation: true, utf8: true } }
+
+    pub fn is_active(&self) -> bool { self.whitespace || self.punctuation || self.utf8 }
+
+    // Whether a word may start or end between the bytes
+    pub fn between(&self, prev: u8, next: u8) -> bool {
+        let transitions = self.whitespace || self.punctuation;
+        let transition = (self.whitespace && prev.is_ascii_whitespace() != next.is_ascii_whitespace())
+                      || (self.punctuation && prev.is_ascii_punctuation() != next.is_ascii_punctuation());
+
+        (transition || !transitions) && !(self.utf8 && is_continuation(next))
+    }
+}
+
+fn is_continuation(byte: u8) -> bool { byte & 0xC0 == 0x80 }
+
+// Whether a word may start or end at the position of buf
+pub fn is_boundary<I: SaIndex>(dict_index: &DictIndex<I>, pos: usize) -> bool {
+    let buf = &dict_index.buf;
+    if pos == 0 || pos >= buf.len() { return true; }
+    dict_index.boundaries.between(buf[pos - 1], buf[pos]) || documents::is_start(&dict_index.doc_starts, pos)
+}
+
+// Whether the occurrence of a word of len bytes at loc starts and ends at boundaries
+pub fn allows<I: SaIndex>(dict_index: &DictIndex<I>, loc: usize, len: usize) -> bool {
+    !dict_index.boundaries.is_active() || (is_boundary(dict_index, loc) && is_boundary(dict_index, loc + len))
+}
+
+// Whether the match of len bytes at loc may fit the boundaries, without looking at every occurrence:
+// they share the first byte and, below the length of the whole match (max_len), the byte after the word
+pub fn may_fit<I: SaIndex>(dict_index: &DictIndex<I>, loc: usize, len: usize, max_len: usize) -> bool {
+    let (boundaries, buf) = (dict_index.boundaries, &dict_index.buf);
+    if !boundaries.is_active() { return true; }
+    if boundaries.utf8 && is_continuation(buf[loc]) { return false; }
+    len == max_len || boundaries.between(buf[loc + len - 1], buf[loc + len])
+}
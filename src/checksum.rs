@@ -0,0 +1,44 @@
+// CRC-32 (IEEE 802.3, reflected polynomial 0xEDB88320) as used by zip, gzip and png
+const CRC32_TABLE: [u32; 256] = build_crc32_table();
+
+const fn build_crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub fn crc32(buf: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for &byte in buf {
+        crc = CRC32_TABLE[usize::from((crc as u8) ^ byte)] ^ (crc >> 8);
+    }
+    !crc
+}
+
+// Adler-32 as used by zlib, also the ID of a zlib preset dictionary
+const ADLER_MOD: u32 = 65521;
+// The most bytes that can be summed before the sums could overflow u32
+const ADLER_BLOCK: usize = 5552;
+
+pub fn adler32(buf: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for block in buf.chunks(ADLER_BLOCK) {
+        for &byte in block {
+            a += u32::from(byte);
+            b += a;
+        }
+        a %= ADLER_MOD;
+        b %= ADLER_MOD;
+    }
+    (b << 16) | a
+}
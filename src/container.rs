@@ -0,0 +1,339 @@
+use std::fmt;
+use std::io::Write;
+
+use crate::checksum::crc32;
+use crate::decode::{self, read_array};
+use crate::error::Result;
+use crate::grammar;
+use crate::parser;
+use crate::range_coder;
+
+// The container holds everything needed to rebuild the original data in a single file
+// All integers are big-endian
+//
+// offset  size  field
+// 0       4     magic "I74R"
+// 4       1     version
+// 5       1     token encoding (see TokenEncoding)
+// 6       1     dictionary encoding (see DictEncoding)
+// 7       8     original length in bytes
+// 15      4     CRC-32 of the original data
+// 19      8     dictionary section length in bytes
+// 27      x     dictionary section, as written by parser::encode_dict or grammar::encode_dict
+// 27+x    8     token section length in bytes
+// 35+x    y     token section, encoded according to the token encoding
+//
+// The alphabet of the tokens is 256 + the number of words
+// The range coded token section starts with the token count as u64, followed by the coded bytes
+// The bit-packed and varint sections are described in parser.rs
+//
+// Version 2 widened the word lengths of the dictionary section from u16 to u32
+// Version 3 added the dictionary encoding, versions 1 and 2 are still read (without it, so with raw dictionaries)
+
+pub const MAGIC: [u8; 4] = *b"I74R";
+pub const VERSION: u8 = 3;
+const HEADER_LEN: usize = 19;
+const HEADER_LEN_V2: usize = 18;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TokenEncoding {
+    U16Be  = 0, // 2 bytes per token, big-endian, for up to 65280 words
+    #[default]
+    Range  = 1, // Adaptive order-0 range coder (see range_coder.rs)
+    Packed = 2, // ceil(log2(alphabet)) bits per token, msb first
+    Varint = 3, // LEB128 varints of the tokens ranked by frequency
+    U32Be  = 4  // 4 bytes per token, big-endian
+}
+
+impl TokenEncoding {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::U16Be),
+            1 => Some(Self::Range),
+            2 => Some(Self::Packed),
+            3 => Some(Self::Varint),
+            4 => Some(Self::U32Be),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum DictEncoding {
+    #[default]
+    Raw = 0,    // The bytes of every word (see parser::encode_dict)
+    Grammar = 1 // Every word parsed with the shorter words (see grammar.rs)
+}
+
+impl DictEncoding {
+    fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Raw),
+            1 => Some(Self::Grammar),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FormatError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownTokenEncoding(u8),
+    UnknownDictEncoding(u8),
+    Truncated,
+    TrailingData(usize),
+    CorruptDictionary,
+    InvalidToken(usize),
+    LengthMismatch { expected: u64, actual: u64 },
+    ChecksumMismatch { expected: u32, actual: u32 }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not an incan74re container (bad magic)"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported container version {v}"),
+            Self::UnknownTokenEncoding(e) => write!(f, "unknown token encoding {e}"),
+            Self::UnknownDictEncoding(e) => write!(f, "unknown dictionary encoding {e}"),
+            Self::Truncated => write!(f, "data is truncated"),
+            Self::TrailingData(n) => write!(f, "{n} unexpected bytes after the token section"),
+            Self::CorruptDictionary => write!(f, "dictionary section is corrupt"),
+            Self::InvalidToken(t) => write!(f, "token {t} is not in the dictionary"),
+            Self::LengthMismatch { expected, actual } => write!(f, "decoded {actual} bytes, expected {expected}"),
+            Self::ChecksumMismatch { expected, actual } => write!(f, "checksum is {actual:08x}, expected {expected:08x}")
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+pub fn write<W>(words: &[Vec<u8>], tokens: &[u32], original: &[u8], encoding: TokenEncoding, dict_encoding: DictEncoding, writer: &mut W) -> Result<()>
+where W: Write {
+    let alphabet = 256 + words.len();
+    if let Some(&token) = tokens.iter().find(|&&token| token as usize >= alphabet) {
+        return Err(FormatError::InvalidToken(token as usize).into());
+    }
+
+    let mut dict_section = Vec::new();
+    match dict_encoding {
+        DictEncoding::Raw => parser::encode_dict(words, &mut dict_section)?,
+        DictEncoding::Grammar => grammar::encode_dict(words, &mut dict_section)?
+    }
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&[VERSION, encoding as u8, dict_encoding as u8])?;
+    writer.write_all(&(original.len() as u64).to_be_bytes())?;
+    writer.write_all(&crc32(original).to_be_bytes())?;
+
+    writer.write_all(&(dict_section.len() as u64).to_be_bytes())?;
+    writer.write_all(&dict_section)?;
+
+    let mut token_section = Vec::new();
+    match encoding {
+        TokenEncoding::U16Be => parser::write_tokens(tokens, &mut token_section)?,
+        TokenEncoding::Range => {
+            token_section.extend_from_slice(&(tokens.len() as u64).to_be_bytes());
+            token_section.extend(range_coder::encode(tokens, alphabet));
+        },
+        TokenEncoding::Packed => parser::write_tokens_packed(tokens, alphabet, &mut token_section)?,
+        TokenEncoding::Varint => parser::write_tokens_varint(tokens, alphabet, &mut token_section)?,
+        TokenEncoding::U32Be => parser::write_tokens_u32(tokens, &mut token_section)?
+    }
+
+    writer.write_all(&(token_section.len() as u64).to_be_bytes())?;
+    Ok(writer.write_all(&token_section)?)
+}
+
+// Reads and validates a container, returning the dictionary words and the original data
+pub fn read(data: &[u8]) -> Result<(Vec<Vec<u8>>, Vec<u8>)> {
+    if data.len() < MAGIC.len() || data[..MAGIC.len()] != MAGIC { return Err(FormatError::BadMagic.into()); }
+    if data.len() < HEADER_LEN_V2 { return Err(FormatError::Truncated.into()); }
+    let version = data[4];
+    if !(1..=VERSION).contains(&version) { return Err(FormatError::UnsupportedVersion(version).into()); }
+    let encoding = TokenEncoding::from_u8(data[5]).ok_or(FormatError::UnknownTokenEncoding(data[5]))?;
+
+    // Versions before 3 have no dictionary encoding, the rest of the header moves up by a byte
+    let (dict_encoding, pos) = match version {
+        1 | 2 => (DictEncoding::Raw, 6),
+        _ => {
+            if data.len() < HEADER_LEN { return Err(FormatError::Truncated.into()); }
+            (DictEncoding::from_u8(data[6]).ok_or(FormatError::UnknownDictEncoding(data[6]))?, 7)
+        }
+    };
+    let original_len = u64::from_be_bytes(read_array(data, pos)?);
+    let checksum = u32::from_be_bytes(read_array(data, pos + 8)?);
+
+    let (dict_section, pos) = read_section(data, pos + 12)?;
+    let (token_section, pos) = read_section(data, pos)?;
+    if pos != data.len() { return Err(FormatError::TrailingData(data.len() - pos).into()); }
+
+    let words = match (version, dict_encoding) {
+        (1, _) => decode::decode_dict_v1(dict_section),
+        (_, DictEncoding::Raw) => decode::decode_dict(dict_section),
+        (_, DictEncoding::Grammar) => grammar::decode_dict(dict_section)
+    };
+    let (words, dict_len) = words.map_err(|_| FormatError::CorruptDictionary)?;
+    if dict_len != dict_section.len() { return Err(FormatError::CorruptDictionary.into()); }
+
+    // The original length limits the decoded bytes and so the tokens, every token is at least a byte
+    let max_len = usize::try_from(original_len).unwrap_or(usize::MAX);
+    let tokens = match encoding {
+        TokenEncoding::U16Be => decode::read_tokens(token_section)?,
+        TokenEncoding::Range => {
+            let count = u64::from_be_bytes(read_array(token_section, 0)?);
+            let count = usize::try_from(count).map_err(|_| FormatError::Truncated)?;
+            if count > max_len { return Err(FormatError::LengthMismatch { expected: original_len, actual: count as u64 }.into()); }
+            range_coder::decode(&token_section[8..], count, 256 + words.len())?
+        },
+        TokenEncoding::Packed => decode::read_tokens_packed(token_section, 256 + words.len())?,
+        TokenEncoding::Varint => decode::read_tokens_varint(token_section)?,
+        TokenEncoding::U32Be => decode::read_tokens_u32(token_section)?
+    };
+    let original = decode::decode(&words, &tokens, max_len)?;
+
+    if original.len() as u64 != original_len {
+        return Err(FormatError::LengthMismatch { expected: original_len, actual: original.len() as u64 }.into());
+    }
+    let actual = crc32(&original);
+    if actual != checksum { return Err(FormatError::ChecksumMismatch { expected: checksum, actual }.into()); }
+
+    Ok((words, original))
+}
+
+// Sections are prefixed with their length as u64
+fn read_section(data: &[u8], pos: usize) -> Result<(&[u8], usize)> {
+    let len = u64::from_be_bytes(read_array(data, pos)?);
+    let start = pos + 8;
+    let end = usize::try_from(len).ok()
+        .and_then(|len| start.checked_add(len))
+        .filter(|&end| end <= data.len())
+        .ok_or(FormatError::Truncated)?;
+
+    Ok((&data[start..end], end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::{Encoded, Options};
+
+    const TOKEN_ENCODINGS: [TokenEncoding; 5] =
+        [TokenEncoding::U16Be, TokenEncoding::Range, TokenEncoding::Packed, TokenEncoding::Varint, TokenEncoding::U32Be];
+
+    fn sample() -> Vec<u8> {
+        let lines = ["GET /index.html 200\n", "GET /style.css 304\n", "POST /login 302\n", "GET /missing 404\n"];
+        (0..400).map(|i| lines[(i * 7 + i / 5) % lines.len()]).collect::<String>().into_bytes()
+    }
+
+    fn container(encoded: &Encoded, encoding: TokenEncoding, dict_encoding: DictEncoding) -> Vec<u8> {
+        let mut data = Vec::new();
+        write(encoded.dictionary.words(), &encoded.tokens, encoded.original, encoding, dict_encoding, &mut data).unwrap();
+        data
+    }
+
+    fn format_error(data: &[u8]) -> FormatError {
+        match read(data) {
+            Err(Error::Format(err)) => err,
+            result => panic!("expected a format error, got {:?}", result.map(|(words, original)| (words.len(), original.len())))
+        }
+    }
+
+    #[test]
+    fn every_encoding_roundtrips() {
+        let buf = sample();
+        let encoded = crate::encode(&buf, Options::default()).unwrap();
+        assert!(!encoded.dictionary.is_empty());
+
+        for encoding in TOKEN_ENCODINGS {
+            for dict_encoding in [DictEncoding::Raw, DictEncoding::Grammar] {
+                let (words, original) = read(&container(&encoded, encoding, dict_encoding)).unwrap();
+                assert_eq!(words, encoded.dictionary.words(), "{encoding:?} {dict_encoding:?}");
+                assert_eq!(original, buf, "{encoding:?} {dict_encoding:?}");
+            }
+
+            let mut data = Vec::new();
+            write(&[], &[], &[], encoding, DictEncoding::Raw, &mut data).unwrap();
+            assert_eq!(read(&data).unwrap(), (Vec::new(), Vec::new()));
+        }
+    }
+
+    #[test]
+    fn large_dictionaries_roundtrip() {
+        // Tokens past u16 and past the DIRECT symbols of the range coder, which escapes them
+        let words: Vec<Vec<u8>> = (0..70000).map(|i| format!("{i:x};").into_bytes()).collect();
+        let tokens: Vec<u32> = (0..70000).rev().chain([69999, 65279, 65280, 0]).map(|i| 256 + i).chain([b'!' as u32]).collect();
+        let original: Vec<u8> = tokens.iter().flat_map(|&token| match token {
+            256.. => words[token as usize - 256].clone(),
+            _ => vec![token as u8]
+        }).collect();
+
+        for encoding in TOKEN_ENCODINGS {
+            let mut data = Vec::new();
+            let result = write(&words, &tokens, &original, encoding, DictEncoding::Raw, &mut data);
+            if encoding == TokenEncoding::U16Be {
+                assert!(matches!(result, Err(Error::TokenOverflow { token: 70255, encoding: TokenEncoding::U16Be })));
+                continue;
+            }
+
+            result.unwrap();
+            assert_eq!(read(&data).unwrap(), (words.clone(), original.clone()), "{encoding:?}");
+        }
+    }
+
+    #[test]
+    fn corrupt_headers_are_rejected() {
+        let buf = sample();
+        let data = container(&crate::encode(&buf, Options::default()).unwrap(), TokenEncoding::Range, DictEncoding::Raw);
+        let with = |pos: usize, byte: u8| { let mut data = data.clone(); data[pos] = byte; data };
+
+        assert_eq!(format_error(&with(0, b'X')), FormatError::BadMagic);
+        assert_eq!(format_error(&with(4, 0)), FormatError::UnsupportedVersion(0));
+        assert_eq!(format_error(&with(4, VERSION + 1)), FormatError::UnsupportedVersion(VERSION + 1));
+        assert_eq!(format_error(&with(5, 9)), FormatError::UnknownTokenEncoding(9));
+        assert_eq!(format_error(&with(6, 9)), FormatError::UnknownDictEncoding(9));
+        assert_eq!(format_error(&data[..10]), FormatError::Truncated);
+    }
+
+    #[test]
+    fn corrupt_sections_are_rejected() {
+        let buf = sample();
+        let data = container(&crate::encode(&buf, Options::default()).unwrap(), TokenEncoding::U32Be, DictEncoding::Raw);
+        let len = data.len();
+
+        assert_eq!(format_error(&data[..(len - 1)]), FormatError::Truncated);
+        assert_eq!(format_error(&[&data[..], b"xy"].concat()), FormatError::TrailingData(2));
+
+        let mut bad_crc = data.clone();
+        bad_crc[15] ^= 1;
+        assert!(matches!(format_error(&bad_crc), FormatError::ChecksumMismatch { .. }));
+
+        let mut bad_token = data.clone();
+        bad_token[(len - 4)..].copy_from_slice(&0x00FF_FFFFu32.to_be_bytes());
+        assert_eq!(format_error(&bad_token), FormatError::InvalidToken(0x00FF_FFFF));
+
+        let mut bad_dict = data.clone();
+        bad_dict[27..31].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(format_error(&bad_dict), FormatError::CorruptDictionary);
+
+        let original_len = u64::from_be_bytes(data[7..15].try_into().unwrap());
+        for claimed in [original_len - 1, original_len + 1] {
+            let mut bad_len = data.clone();
+            bad_len[7..15].copy_from_slice(&claimed.to_be_bytes());
+            assert!(matches!(format_error(&bad_len), FormatError::LengthMismatch { expected, .. } if expected == claimed));
+        }
+    }
+
+    #[test]
+    fn expansion_stops_at_the_original_length() {
+        // A few KiB of tokens of a 64 KiB word, claiming 10 bytes of original data
+        let mut data = Vec::new();
+        write(&[vec![b'a'; 1 << 16]], &[256; 1 << 12], b"0123456789", TokenEncoding::U32Be, DictEncoding::Raw, &mut data).unwrap();
+        assert_eq!(format_error(&data), FormatError::LengthMismatch { expected: 10, actual: 1 << 16 });
+
+        let mut data = Vec::new();
+        write(&[], &[b'a' as u32; 1000], b"0123456789", TokenEncoding::Range, DictEncoding::Raw, &mut data).unwrap();
+        assert_eq!(format_error(&data), FormatError::LengthMismatch { expected: 10, actual: 1000 });
+    }
+}
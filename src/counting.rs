@@ -1,40 +1,43 @@
-use std::ops::Neg;
+use crate::alignment;
+use crate::boundaries;
 use crate::match_finder::Match;
 use crate::incan74re::DictIndex;
+use crate::index::SaIndex;
+use crate::records;
 
-pub fn count(m: &mut Match, dict_index: &DictIndex) -> (u32, usize) {
+pub fn count<I: SaIndex>(m: &mut Match<I>, dict_index: &DictIndex<I>) -> (u64, usize) {
     if m.self_ref { count_slow(m, dict_index) }
     else          { count_fast(m, dict_index) }
 }
 
-// Casts here are safe just unproven because libsais uses i32-s for the SA
-fn count_fast(m: &mut Match, dict_index: &DictIndex) -> (u32, usize) {
+// Casts here are safe just unproven because libsais uses signed integers for the SA
+fn count_fast<I: SaIndex>(m: &mut Match<I>, dict_index: &DictIndex<I>) -> (u64, usize) {
     let mut count = 0;
-    let effective_len = i32::from(m.len) - 1;
+    let effective_len = I::from_usize(m.len as usize - 1);
 
-    let last_match = dict_index.sa[m.sa_index as usize] as usize;
+    let last_match = dict_index.sa[m.sa_index.as_usize()].as_usize();
     let range = m.get_range();
 
     // TODO: Try unroll?
     // TODO: Prefetch?
     for &loc in dict_index.sa[range].iter() {
-        if dict_index.offsets[loc as usize] >= effective_len { count += 1; }
+        if dict_index.offsets[loc.as_usize()] >= effective_len && fits(dict_index, loc.as_usize(), m.len as usize) { count += 1; }
     }
 
     (count, last_match)
 }
 
-// Casts here are safe just unproven because libsais uses i32-s for the SA
-fn count_slow(m: &mut Match, dict_index: &DictIndex) -> (u32, usize) {
+// Casts here are safe just unproven because libsais uses signed integers for the SA
+fn count_slow<I: SaIndex>(m: &mut Match<I>, dict_index: &DictIndex<I>) -> (u64, usize) {
     let range = m.get_range();
-    let mut locations = vec![0; range.len()];
+    let mut locations = vec![I::ZERO; range.len()];
     locations.copy_from_slice(&dict_index.sa[range]);
     locations.sort_unstable();
 
-    let effective_len = i32::from(m.len) - 1;
+    let effective_len = I::from_usize(m.len as usize - 1);
     let mut count = 0;
     let mut flag = false;
-    let mut last_match = i32::from(m.len).neg(); // 0-len
+    let mut last_match = -I::from_usize(m.len as usize); // 0-len
 
     for loc in locations {
         // TODO: Optimize branching? -> there're no branches in the loop,
@@ -44,12 +47,17 @@ fn count_slow(m: &mut Match, dict_index: &DictIndex) -> (u32, usize) {
         // but perhaps the branch predictor is fine as it is and can even speculatively prefetch offsets[loc]
         if loc <= last_match + effective_len { flag = true; continue; }
 
-        if dict_index.offsets[loc as usize] >= effective_len {
+        if dict_index.offsets[loc.as_usize()] >= effective_len && fits(dict_index, loc.as_usize(), m.len as usize) {
             count += 1;
             last_match = loc;
         }
     }
 
     m.self_ref = flag;
-    (count, last_match.try_into().unwrap_or(usize::MAX))
+    (count, if last_match < I::ZERO { usize::MAX } else { last_match.as_usize() })
+}
+
+// Whether the occurrence fits the boundaries, the records and the alignment, split picks the same occurrences
+pub fn fits<I: SaIndex>(dict_index: &DictIndex<I>, loc: usize, len: usize) -> bool {
+    boundaries::allows(dict_index, loc, len) && records::allows(dict_index, loc, len) && alignment::allows(dict_index, loc, len)
 }
@@ -0,0 +1,132 @@
+use crate::container::FormatError;
+use crate::error::Result;
+use crate::parser;
+
+// Reverses the outputs of parser::encode_dict and the parser::write_tokens* functions
+
+// Reads the dictionary as written by parser::encode_dict
+// Returns the words in file order (so dict[index-256] decodes a word token) and the number of bytes read
+pub fn decode_dict(data: &[u8]) -> Result<(Vec<Vec<u8>>, usize)> {
+    read_dict::<4>(data)
+}
+
+// Version 1 containers stored the word lengths as u16
+pub fn decode_dict_v1(data: &[u8]) -> Result<(Vec<Vec<u8>>, usize)> {
+    read_dict::<2>(data)
+}
+
+// N is the size of the big-endian word lengths
+fn read_dict<const N: usize>(data: &[u8]) -> Result<(Vec<Vec<u8>>, usize)> {
+    let count = u32::from_be_bytes(read_array(data, 0)?);
+    // Every word takes at least N bytes, don't trust the count for the allocation
+    let mut dict = Vec::with_capacity((count as usize).min(data.len() / N));
+    let mut pos = 4;
+
+    for _ in 0..count {
+        let len = read_array::<N>(data, pos)?.iter().fold(0usize, |len, &byte| (len << 8) | usize::from(byte));
+        pos += N;
+        let word = pos.checked_add(len).and_then(|end| data.get(pos..end)).ok_or(FormatError::Truncated)?;
+        dict.push(word.to_vec());
+        pos += len;
+    }
+
+    Ok((dict, pos))
+}
+
+// Reads the big-endian u16 token stream written by parser::write_tokens
+pub fn read_tokens(data: &[u8]) -> Result<Vec<u32>> {
+    if !data.len().is_multiple_of(2) { return Err(FormatError::Truncated.into()); }
+    Ok(data.chunks_exact(2).map(|token| u32::from(u16::from_be_bytes([token[0], token[1]]))).collect())
+}
+
+pub fn read_tokens_u32(data: &[u8]) -> Result<Vec<u32>> {
+    if !data.len().is_multiple_of(4) { return Err(FormatError::Truncated.into()); }
+    Ok(data.chunks_exact(4).map(|token| u32::from_be_bytes([token[0], token[1], token[2], token[3]])).collect())
+}
+
+// Reads the tokens packed by parser::write_tokens_packed, the width follows from the alphabet
+pub fn read_tokens_packed(data: &[u8], alphabet: usize) -> Result<Vec<u32>> {
+    let width = parser::bits_per_token(alphabet);
+    let count = data.len() * 8 / width as usize;
+    let mut tokens = Vec::with_capacity(count);
+    let mut acc = 0u64;
+    let mut bits = 0;
+
+    for &byte in data {
+        acc = (acc << 8) | u64::from(byte);
+        bits += 8;
+        if bits >= width {
+            bits -= width;
+            tokens.push((acc >> bits) as u32);
+            acc &= (1 << bits) - 1;
+        }
+    }
+
+    // Only the zero padding of the last byte may be left
+    if bits >= 8 || acc != 0 { return Err(FormatError::Truncated.into()); }
+    Ok(tokens)
+}
+
+// Reads the ranks written by parser::write_tokens_varint and maps them back to tokens
+pub fn read_tokens_varint(data: &[u8]) -> Result<Vec<u32>> {
+    let (distinct, mut pos) = read_varint(data, 0)?;
+    // Every distinct token takes at least a byte
+    let mut by_rank = Vec::with_capacity((distinct as usize).min(data.len()));
+    for _ in 0..distinct {
+        let (token, next) = read_varint(data, pos)?;
+        by_rank.push(u32::try_from(token).map_err(|_| FormatError::InvalidToken(token as usize))?);
+        pos = next;
+    }
+
+    let mut tokens = Vec::with_capacity(data.len() - pos);
+    while pos < data.len() {
+        let (rank, next) = read_varint(data, pos)?;
+        let token = usize::try_from(rank).ok().and_then(|rank| by_rank.get(rank)).ok_or(FormatError::InvalidToken(rank as usize))?;
+        tokens.push(*token);
+        pos = next;
+    }
+
+    Ok(tokens)
+}
+
+// Returns the value and the position after it
+pub fn read_varint(data: &[u8], mut pos: usize) -> Result<(u64, usize)> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(pos).ok_or(FormatError::Truncated)?;
+        pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 { return Ok((value, pos)); }
+    }
+
+    Err(FormatError::Truncated.into())
+}
+
+// Expands the tokens produced by parser::parse back into the original bytes
+// Tokens in the range [0 .. 255] are raw literals, tokens in [256 .. 256 + dict.len()] are dictionary words
+// Decoding stops as soon as the bytes exceed max_len (the original length of a container), a few tokens of long
+// words would otherwise expand to any size
+pub fn decode(dict: &[Vec<u8>], tokens: &[u32], max_len: usize) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(tokens.len().min(max_len));
+    let too_long = |len: usize| FormatError::LengthMismatch { expected: max_len as u64, actual: len as u64 };
+
+    for &token in tokens {
+        let token = token as usize;
+        if token < 256 {
+            if buf.len() == max_len { return Err(too_long(buf.len() + 1).into()); }
+            buf.push(token as u8);
+            continue;
+        }
+
+        let word = dict.get(token-256).ok_or(FormatError::InvalidToken(token))?;
+        if word.len() > max_len - buf.len() { return Err(too_long(buf.len() + word.len()).into()); }
+        buf.extend_from_slice(word);
+    }
+
+    Ok(buf)
+}
+
+pub fn read_array<const N: usize>(data: &[u8], pos: usize) -> Result<[u8; N]> {
+    let bytes = data.get(pos..(pos + N)).ok_or(FormatError::Truncated)?;
+    Ok(bytes.try_into().unwrap())
+}
@@ -0,0 +1,92 @@
+// Lays out words as a raw preset dictionary for DEFLATE (zlib's deflateSetDictionary), within a byte budget
+// DEFLATE reaches back 32 KiB and shorter distances take fewer bits, so the best words go to the end
+//
+// The words are placed from the best down, each in front of the ones placed so far:
+// a word that's already in the buffer is skipped and a word whose end matches the start of the buffer
+// only adds the bytes before the overlap, words that don't fit are skipped and smaller ones are still tried
+
+pub const WINDOW: usize = 32 << 10;
+
+// Scores holds the value of each word, higher is better
+pub fn build(words: &[&[u8]], scores: &[f64], budget: usize) -> Vec<u8> {
+    let mut order: Vec<usize> = (0..words.len()).collect();
+    order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+
+    // Built back to front, reversed at the end
+    let mut buf: Vec<u8> = Vec::with_capacity(budget);
+    for index in order {
+        let word = words[index];
+        if word.is_empty() || contains(&buf, word) { continue; }
+
+        let overlap = (1..word.len().min(buf.len() + 1)).rev()
+            .find(|&len| word[(word.len() - len)..].iter().eq(buf.iter().rev().take(len)))
+            .unwrap_or(0);
+        let added = word.len() - overlap;
+        if buf.len() + added > budget { continue; }
+
+        buf.extend(word[..added].iter().rev());
+    }
+
+    buf.reverse();
+    buf
+}
+
+// Whether the reversed buffer holds the word
+fn contains(reversed: &[u8], word: &[u8]) -> bool {
+    let last = word[word.len() - 1];
+    reversed.len() >= word.len() && (0..=(reversed.len() - word.len()))
+        .filter(|&pos| reversed[pos] == last)
+        .any(|pos| reversed[pos..(pos + word.len())].iter().eq(word.iter().rev()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::adler32;
+    use crate::Options;
+
+    #[test]
+    fn adler32_matches_zlib() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+
+        // Past a block of ADLER_BLOCK bytes, where the sums are reduced
+        let buf = vec![0xffu8; 100_000];
+        let (mut a, mut b) = (1u64, 0u64);
+        for &byte in &buf { a = (a + u64::from(byte)) % 65521; b = (b + a) % 65521; }
+        assert_eq!(adler32(&buf), ((b << 16) | a) as u32);
+    }
+
+    #[test]
+    fn best_words_end_the_buffer() {
+        let words: [&[u8]; 4] = [b"hello", b"world", b"lo w", b"xyz"];
+        let scores = [1f64, 3f64, 2f64, 0.5];
+        assert_eq!(build(&words, &scores, 9), b"lo world");
+        assert_eq!(build(&words, &scores, 11), b"hello world");
+        assert_eq!(build(&words, &scores, 14), b"xyzhello world");
+        // Smaller words still fit after a larger one didn't
+        assert_eq!(build(&words, &scores, 4), b"lo w");
+    }
+
+    #[test]
+    fn exports_fit_the_window() {
+        // Over 40 KiB of distinct words, the best first
+        let items: Vec<Vec<u8>> = (0..2000u64).map(|item| format!("item-{item:05}-{:x} ", item * item * 7919).into_bytes()).collect();
+        let words: Vec<&[u8]> = items.iter().map(Vec::as_slice).collect();
+        let scores: Vec<f64> = (0..words.len()).map(|item| 1f64 / (item as f64 + 1f64)).collect();
+        let preset = build(&words, &scores, WINDOW);
+        assert!(preset.len() <= WINDOW && preset.len() > WINDOW - 32, "{} bytes", preset.len());
+        assert!(preset.ends_with(words[0]));
+
+        let lines = ["GET /index.html 200\n", "GET /style.css 304\n", "POST /login 302\n", "GET /missing 404\n"];
+        let buf = (0..400).map(|i| lines[(i * 7 + i / 5) % lines.len()]).collect::<String>().into_bytes();
+        let encoded = crate::encode(&buf, Options::default()).unwrap();
+        let best = encoded.picks.iter().max_by(|a, b| (a.rank * a.count as f64).total_cmp(&(b.rank * b.count as f64))).unwrap();
+        for budget in [WINDOW, best.len as usize + 8] {
+            let mut preset = Vec::new();
+            let id = encoded.write_deflate_dict(budget, &mut preset).unwrap();
+            assert!(preset.len() <= budget && preset.ends_with(&buf[best.get_range()]), "{} bytes", preset.len());
+            assert_eq!(id, adler32(&preset));
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use crate::backend::Backend;
+use crate::error::{check_len, Result};
+use crate::index::SaIndex;
+
+// Training over several documents (e.g. the sample files of a corpus) at once
+// The documents are concatenated into the buffer of the DictIndex, starts holds the position of each one in it
+//
+// The suffix array is generalized: it's built over a text with a unique separator after every document,
+// the separator of document d is the symbol d and the byte b is the symbol b + D (D documents)
+// Separators sort first, so a suffix sorts by its bytes up to the end of its document, the common prefixes
+// (and so the matches) never reach into the next document and all occurrences of a string share an SA range
+
+pub fn concat(docs: &[&[u8]]) -> (Vec<u8>, Vec<usize>) {
+    let mut buf = Vec::with_capacity(docs.iter().map(|doc| doc.len()).sum());
+    let mut starts = Vec::with_capacity(docs.len());
+    for doc in docs {
+        starts.push(buf.len());
+        buf.extend_from_slice(doc);
+    }
+
+    (buf, starts)
+}
+
+// The generalized SA of the documents in buf, holding positions into buf (the separators are left out)
+pub fn suffix_array<I: SaIndex, B: Backend>(buf: &[u8], starts: &[usize]) -> Result<Vec<I>> {
+    let docs = starts.len();
+    check_len::<I>(buf.len() + docs + 256, I::MAX)?;
+
+    let mut text = Vec::with_capacity(buf.len() + docs);
+    for doc in 0..docs {
+        text.extend(buf[starts[doc]..end(starts, doc, buf.len())].iter().map(|&byte| I::from_usize(byte as usize + docs)));
+        text.push(I::from_usize(doc));
+    }
+
+    let mut sa = B::suffix_array_int(&mut text, docs + 256)?;
+
+    // The text is reused to map its positions to buf, every byte of document d is d positions further in the text
+    for doc in 0..docs {
+        for pos in starts[doc]..end(starts, doc, buf.len()) { text[pos + doc] = I::from_usize(pos); }
+    }
+    sa.drain(..docs);
+    sa.iter_mut().for_each(|pos| *pos = text[pos.as_usize()]);
+
+    Ok(sa)
+}
+
+// The LCP array of the generalized SA (Kasai et al.), a common prefix ends with the shorter of the documents
+pub fn lcp_array<I: SaIndex>(buf: &[u8], sa: &[I], starts: &[usize]) -> Vec<I> {
+    let n = buf.len();
+    let mut rank = vec![I::ZERO; n];
+    for (i, &suffix) in sa.iter().enumerate() {
+        rank[suffix.as_usize()] = I::from_usize(i);
+    }
+
+    let mut lcp = vec![I::ZERO; n];
+    let mut h = 0;
+    for i in 0..n {
+        let r = rank[i].as_usize();
+        if r + 1 >= n { h = 0; continue; }
+
+        let j = sa[r + 1].as_usize();
+        let (end_i, end_j) = (end(starts, document(starts, i), n), end(starts, document(starts, j), n));
+        while i + h < end_i && j + h < end_j && buf[i + h] == buf[j + h] { h += 1; }
+        lcp[r] = I::from_usize(h);
+        h = h.saturating_sub(1);
+    }
+
+    lcp
+}
+
+// The number of documents the sorted locations fall into
+pub fn count(locations: &[usize], starts: &[usize]) -> u64 {
+    let mut docs = 0;
+    let mut doc_end = 0;
+    for &loc in locations {
+        if loc < doc_end { continue; }
+        docs += 1;
+        doc_end = end(starts, document(starts, loc), usize::MAX);
+    }
+
+    docs
+}
+
+// The document holding the position (the last one starting at or before it, empty documents are skipped)
+pub fn document(starts: &[usize], pos: usize) -> usize {
+    starts.partition_point(|&start| start <= pos).saturating_sub(1)
+}
+
+fn end(starts: &[usize], doc: usize, len: usize) -> usize {
+    starts.get(doc + 1).copied().unwrap_or(len)
+}
+
+// True if a document starts at the position, nothing precedes it for the match finder
+pub fn is_start(starts: &[usize], pos: usize) -> bool {
+    starts.binary_search(&pos).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BuildOptions, DictIndex, Dictionary, Options};
+    use crate::match_finder::{self, Match};
+
+    // Each document ends with the start of the next, "abcdefghij" repeats only across the boundaries
+    const DOCS: [&[u8]; 3] = [b"abcdef", b"ghijabcd", b"efghij"];
+
+    #[test]
+    fn matches_stay_in_their_document() {
+        let dict_index = crate::initialize_documents::<i32>(&DOCS, &Options::default()).unwrap();
+        let DictIndex { buf, sa, doc_starts, .. } = &dict_index;
+        let lcp = lcp_array(buf, sa, doc_starts);
+        assert!(lcp.iter().all(|&len| len <= 4), "{lcp:?}");
+
+        let mut matches: Vec<Match<i32>> = Vec::new();
+        match_finder::generate(&mut matches, lcp, &dict_index, &Options::default());
+        assert!(!matches.is_empty());
+        for m in &matches {
+            for &loc in &sa[m.get_range()] {
+                let loc = loc as usize;
+                assert_eq!(document(doc_starts, loc), document(doc_starts, loc + m.len as usize - 1), "{:?} at {loc}", &buf[loc..(loc + m.len as usize)]);
+            }
+        }
+
+        let dictionary = Dictionary::train_documents(&DOCS, Options { build: BuildOptions::new().byte_overhead(0f64), ..Default::default() }).unwrap();
+        assert!(!dictionary.is_empty());
+        for word in dictionary.words() {
+            assert!(DOCS.iter().any(|doc| doc.windows(word.len()).any(|window| window == &word[..])), "{word:?}");
+        }
+    }
+
+    #[test]
+    fn documents_are_counted_once() {
+        let starts = [0, 20, 30];
+        assert_eq!(count(&[0, 12, 35], &starts), 2);
+        assert_eq!(count(&[3, 8, 12], &starts), 1);
+        assert_eq!(count(&[19, 20, 29, 30], &starts), 3);
+
+        // "the cat" twice in the first document and once in the last
+        let docs: [&[u8]; 3] = [b"the cat and the cat", b"a dog", b"the cat"];
+        let dictionary = Dictionary::train_documents(&docs, Options { build: BuildOptions::new().byte_overhead(0f64), ..Default::default() }).unwrap();
+        let cat = dictionary.words().iter().position(|word| word == b"the cat").unwrap();
+        assert_eq!(dictionary.documents()[cat], 2);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::container::TokenEncoding;
+use crate::error::{Error, Result};
+use crate::incan74re::MAX_WORDS;
+use crate::parser;
+use crate::trie::Trie;
+use crate::{Dictionary, Encoded};
+
+// Parses new data with a trained (or loaded) dictionary, parser::parse only works on the data the dictionary was
+// built from, as it reads the words off the offsets array that split wrote
+// The parse is greedy, the longest word at each position, otherwise a literal
+// The tokens are the same as those of parse, so containers decode as usual
+// tokenize_optimal finds the cheapest parse instead, by dynamic programming over the words that start at each position
+#[derive(Clone, Debug)]
+pub struct Encoder {
+    dictionary: Dictionary,
+    trie: Trie
+}
+
+impl Encoder {
+    pub fn new(dictionary: Dictionary) -> Result<Self> {
+        if dictionary.len() > MAX_WORDS { return Err(Error::TooManyWords { max: MAX_WORDS }); }
+
+        let mut trie = Trie::new();
+        for (index, word) in dictionary.words().iter().enumerate() { trie.insert(word, index); }
+
+        Ok(Self { dictionary, trie })
+    }
+
+    pub fn dictionary(&self) -> &Dictionary { &self.dictionary }
+
+    pub fn tokenize(&self, buf: &[u8]) -> Vec<u32> {
+        let mut tokens = Vec::with_capacity(buf.len() / 2);
+        let mut pos = 0;
+
+        while pos < buf.len() {
+            match self.trie.prefixes(&buf[pos..]).last() {
+                Some((len, word)) => {
+                    tokens.push((256 + word) as u32);
+                    pos += len;
+                },
+                None => {
+                    tokens.push(u32::from(buf[pos]));
+                    pos += 1;
+                }
+            }
+        }
+
+        tokens
+    }
+
+    // The parse with the lowest total cost, costs holds the bits of every token (see CostModel::costs)
+    pub fn tokenize_optimal(&self, buf: &[u8], costs: &[f64]) -> Vec<u32> {
+        let n = buf.len();
+        // cost[i] is the cost of buf[..i], last[i] the token that ends there
+        let mut cost = vec![f64::INFINITY; n + 1];
+        let mut last = vec![0u32; n + 1];
+        cost[0] = 0f64;
+
+        for start in 0..n {
+            let literal = cost[start] + costs[usize::from(buf[start])];
+            if literal < cost[start + 1] {
+                cost[start + 1] = literal;
+                last[start + 1] = u32::from(buf[start]);
+            }
+
+            for (len, word) in self.trie.prefixes(&buf[start..]) {
+                let token = 256 + word;
+                let word_cost = cost[start] + costs[token];
+                if word_cost < cost[start + len] {
+                    cost[start + len] = word_cost;
+                    last[start + len] = token as u32;
+                }
+            }
+        }
+
+        let words = self.dictionary.words();
+        let mut tokens = Vec::new();
+        let mut end = n;
+        while end > 0 {
+            let token = last[end];
+            tokens.push(token);
+            end -= if token < 256 { 1 } else { words[token as usize - 256].len() };
+        }
+
+        tokens.reverse();
+        tokens
+    }
+
+    // The encoded data holds a copy of the dictionary, it has no picks
+    pub fn encode<'a>(&self, buf: &'a [u8]) -> Encoded<'a> {
+        Encoded { dictionary: self.dictionary.clone(), tokens: self.tokenize(buf), original: buf, picks: Vec::new() }
+    }
+}
+
+// How the optimal parse prices the tokens
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CostModel {
+    FixedWidth(u32), // Every token takes the same bits (TokenEncoding::U16Be, U32Be or Packed)
+    Entropy          // Order-0 entropy of the tokens, what the range coder gets close to
+}
+
+impl CostModel {
+    // The model that matches how the tokens will be written
+    pub fn for_encoding(encoding: TokenEncoding, alphabet: usize) -> Self {
+        match encoding {
+            TokenEncoding::U16Be => Self::FixedWidth(16),
+            TokenEncoding::U32Be => Self::FixedWidth(32),
+            TokenEncoding::Packed => Self::FixedWidth(parser::bits_per_token(alphabet)),
+            TokenEncoding::Range | TokenEncoding::Varint => Self::Entropy
+        }
+    }
+
+    // Bits of every token of the alphabet, Entropy takes the frequencies from tokens
+    // Tokens that don't occur count as 1/2, so the parse can still pick them
+    pub fn costs(&self, tokens: &[u32], alphabet: usize) -> Vec<f64> {
+        match *self {
+            Self::FixedWidth(bits) => vec![f64::from(bits); alphabet],
+            Self::Entropy => {
+                let counts = count_tokens(tokens, alphabet);
+                let total = tokens.len() as f64 + alphabet as f64 / 2f64;
+                counts.iter().map(|&count| (total / (count as f64).max(0.5)).log2()).collect()
+            }
+        }
+    }
+
+    // Size of the tokens in bytes
+    pub fn size(&self, tokens: &[u32], alphabet: usize) -> f64 {
+        match *self {
+            Self::FixedWidth(bits) => tokens.len() as f64 * f64::from(bits) / 8f64,
+            Self::Entropy => {
+                let n = tokens.len() as f64;
+                count_tokens(tokens, alphabet).iter()
+                    .filter(|&&count| count > 0)
+                    .map(|&count| count as f64 * (n / count as f64).log2())
+                    .sum::<f64>() / 8f64
+            }
+        }
+    }
+}
+
+fn count_tokens(tokens: &[u32], alphabet: usize) -> Vec<u64> {
+    let mut counts = vec![0u64; alphabet];
+    for &token in tokens { counts[token as usize] += 1; }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Options, Records, Whole};
+
+    // The same lines in a different order for every day
+    fn log(day: usize) -> Vec<u8> {
+        let lines = ["GET /index.html 200\n", "GET /style.css 304\n", "POST /login 302\n", "GET /missing 404\n"];
+        (0..300).map(|i| lines[(i * 7 + i / (4 + day)) % lines.len()]).collect::<String>().into_bytes()
+    }
+
+    #[test]
+    fn saved_dictionaries_encode_unseen_data() {
+        let buf = log(1);
+        // Whole lines fit any order of the lines
+        let records = Records::new(b"\n", Whole::Lines(b'\n'));
+        let trained = crate::encode(&buf, Options { records, ..Default::default() }).unwrap();
+        let mut saved = Vec::new();
+        trained.dictionary.write(&mut saved).unwrap();
+
+        let (dictionary, len) = Dictionary::read(&saved).unwrap();
+        assert_eq!((dictionary.words(), len), (trained.dictionary.words(), saved.len()));
+        let encoder = Encoder::new(dictionary).unwrap();
+
+        let unseen = log(2);
+        let encoded = encoder.encode(&unseen);
+        assert!(encoded.tokens.len() < unseen.len() / 8, "{} tokens", encoded.tokens.len());
+        for encoding in [TokenEncoding::Range, TokenEncoding::U16Be] {
+            let mut container = Vec::new();
+            encoded.write_with(encoding, Default::default(), &mut container).unwrap();
+            assert_eq!(crate::read_container(&container).unwrap(), (encoder.dictionary().clone(), unseen.clone()));
+        }
+    }
+
+    fn bits(tokens: &[u32], costs: &[f64]) -> f64 { tokens.iter().map(|&token| costs[token as usize]).sum() }
+
+    #[test]
+    fn greedy_can_be_strictly_worse() {
+        // Greedy takes "abc", then two literals, the optimal parse is "ab" "cde"
+        let encoder = Encoder::new(Dictionary::from_words(vec![b"abc".to_vec(), b"ab".to_vec(), b"cde".to_vec()])).unwrap();
+        assert_eq!(encoder.tokenize(b"abcde"), [256, b'd' as u32, b'e' as u32]);
+
+        let costs = CostModel::FixedWidth(16).costs(&[], 259);
+        assert_eq!(encoder.tokenize_optimal(b"abcde", &costs), [257, 258]);
+    }
+
+    #[test]
+    fn optimal_never_costs_more_than_greedy() {
+        let buf = log(1);
+        let dictionary = crate::encode(&buf, Options::default()).unwrap().dictionary;
+        let alphabet = 256 + dictionary.len();
+        let encoder = Encoder::new(dictionary).unwrap();
+
+        for unseen in [log(1), log(2), b"GET /login 200\nPOST /index.html 404\n".repeat(20)] {
+            let greedy = encoder.tokenize(&unseen);
+            for model in [CostModel::FixedWidth(16), CostModel::for_encoding(TokenEncoding::Packed, alphabet), CostModel::Entropy] {
+                let costs = model.costs(&greedy, alphabet);
+                let optimal = encoder.tokenize_optimal(&unseen, &costs);
+                // Parses of equal cost may sum their costs in a different order
+                assert!(bits(&optimal, &costs) <= bits(&greedy, &costs) + 1e-6, "{model:?}");
+                assert_eq!(crate::decode(encoder.dictionary(), &optimal).unwrap(), unseen);
+            }
+        }
+    }
+}
@@ -1,66 +1,72 @@
-use crate::counting::count;
-use crate::incan74re::{DictIndex, Word};
-use crate::match_finder::Match;
+use crate::incan74re::{count_bytes, DictIndex, Word, COUNTS_STEP};
+use crate::index::SaIndex;
+use crate::ranking::{xlog2, Candidate, RankingStrategy};
 
 // TODO: Add get_entropy?
 
-pub fn rank(m: &mut Match, dict_index: &mut DictIndex) -> Option<Word> {
-    // From match_finder we know len >= 2 and sa_count >= 2 (if m is valid)
-    let (count, loc) = count(m, dict_index);
-    if count < 2 {
-        m.is_valid = false;
-        return None;
-    }
+// The default strategy: the gain in order-0 entropy of the text (in bits) minus the dictionary overhead
+// The scratch holds the symbol counts of the candidate, it must be all zeroes and is left zeroed on return
+// Long candidates (repeats of many KiB) take their symbol counts from the byte counts of the index instead
+#[derive(Clone, Copy, Default, Debug)]
+pub struct EntropyRanking;
 
-    let len = m.len;
-    let slice = &dict_index.buf[loc..(loc + len as usize)];
-    for &sym in slice {
-        dict_index.sym_counts[sym as usize] += 1f64;
-    }
+impl RankingStrategy for EntropyRanking {
+    type Scratch = [f64; 256];
+    fn scratch(&self) -> [f64; 256] { [0f64; 256] }
 
-    let mut rank = 0f64;
-    let count_prec = f64::from(count);
-    let n_prec = f64::from(dict_index.n);
-    let len_prec = f64::from(len);
-    let n1 = n_prec - count_prec * (len_prec - 1f64);
+    fn rank<I: SaIndex>(&self, candidate: &Candidate, dict_index: &DictIndex<I>, sym_counts: &mut [f64; 256]) -> f64 {
+        let slice = candidate.bytes;
+        let mut rank = 0f64;
+        // Counts are exact in f64 up to 2^53
+        let count_prec = candidate.count as f64;
+        let n_prec = candidate.n as f64;
+        let len_prec = slice.len() as f64;
+        let n1 = n_prec - count_prec * (len_prec - 1f64);
 
-    for sym in slice {
-        let sym_index = *sym as usize;
-        let sym_count = dict_index.sym_counts[sym_index];
-        // TODO: Try to find a branchless solution to this?
-        // Maybe zero out some stuff, would that be faster? (cx:=0, cxw:=0 would make rank_d=0)
-        // but log2(0.0) = NaN and 0*NaN -> NaN
-        if sym_count == 0f64 { continue; }
-        dict_index.sym_counts[sym_index] = 0f64;
+        if slice.len() > 2 * COUNTS_STEP {
+            let counts = count_bytes(dict_index, candidate.location..(candidate.location + slice.len()));
+            for (sym_index, &sym_count) in counts.iter().enumerate().filter(|&(_, &sym_count)| sym_count > 0) {
+                let cx = dict_index.model[sym_index];
+                rank += xlog2(cx - f64::from(sym_count) * count_prec) - xlog2(cx);
+            }
 
-        let cx = dict_index.model[sym_index];
-        let cxw = cx - sym_count * count_prec;
-        rank += cxw * cxw.log2() - cx * cx.log2();
-    }
+            return rank - candidate.overhead + xlog2(count_prec) - xlog2(n1) + xlog2(n_prec);
+        }
 
-    rank -= 8f64 * (len_prec + 1f64); // Dictionary overhead
-    rank += count_prec * count_prec.log2();
-    rank -= n1 * n1.log2();
-    rank += n_prec * n_prec.log2();
+        for &sym in slice {
+            sym_counts[sym as usize] += 1f64;
+        }
 
-    if rank <= 0f64 || rank.is_nan() {
-        m.is_valid = false;
-        return None;
-    }
+        for sym in slice {
+            let sym_index = *sym as usize;
+            let sym_count = sym_counts[sym_index];
+            // TODO: Try to find a branchless solution to this?
+            // Maybe zero out some stuff, would that be faster? (cx:=0, cxw:=0 would make rank_d=0)
+            // but log2(0.0) = NaN and 0*NaN -> NaN
+            if sym_count == 0f64 { continue; }
+            sym_counts[sym_index] = 0f64;
+
+            let cx = dict_index.model[sym_index];
+            let cxw = cx - sym_count * count_prec;
+            rank += xlog2(cxw) - cx * cx.log2();
+        }
 
-    Some(Word {
-        location: loc, len,
-        sa_index: m.sa_index, sa_count: m.sa_count,
-        count, rank
-    })
+        rank -= candidate.overhead;
+        rank += count_prec * count_prec.log2();
+        rank -= n1 * n1.log2();
+        rank += n_prec * n_prec.log2();
+
+        rank
+    }
 }
 
-pub fn update_model(word: &Word, dict_index: &mut DictIndex) {
-    let count = f64::from(word.count);
+// The byte model and the symbol count are kept for every strategy
+pub fn update_model<I: SaIndex>(word: &Word, dict_index: &mut DictIndex<I>) {
+    let count = word.count as f64;
     let slice = &dict_index.buf[word.get_range()];
     for &sym in slice {
         dict_index.model[usize::from(sym)] -= count;
     }
 
-    dict_index.n -= word.count * (u32::from(word.len) - 1);
+    dict_index.n -= word.count * (u64::from(word.len) - 1);
 }
@@ -0,0 +1,58 @@
+use std::{fmt, io};
+
+use crate::container::{FormatError, TokenEncoding};
+
+#[derive(Debug)]
+pub enum Error {
+    // A libsais function returned a non-zero code
+    Libsais { function: &'static str, code: i64 },
+    Io(io::Error),
+    // The input (or a part of the output) doesn't fit the limits of the index or the format
+    InputTooLarge { len: usize, max: usize },
+    // A dictionary has more words than tokens can address (build_dictionary stops at MAX_WORDS)
+    TooManyWords { max: usize },
+    // A token is too large for the token encoding, the dictionary has too many words for it
+    TokenOverflow { token: u32, encoding: TokenEncoding },
+    // The words of a zstd dictionary are shorter than its repeat offsets
+    DictionaryTooSmall { len: usize, min: usize },
+    Format(FormatError)
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Libsais { function, code } => write!(f, "{function} failed with code {code}"),
+            Self::Io(err) => write!(f, "{err}"),
+            Self::InputTooLarge { len, max } => write!(f, "input of {len} exceeds the limit of {max}"),
+            Self::TooManyWords { max } => write!(f, "dictionary exceeds the limit of {max} words"),
+            Self::TokenOverflow { token, encoding } => write!(f, "token {token} doesn't fit the {encoding:?} token encoding, pick a wider one (e.g. u32 or range)"),
+            Self::DictionaryTooSmall { len, min } => write!(f, "dictionary content of {len} bytes is below the minimum of {min}"),
+            Self::Format(err) => write!(f, "{err}")
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Format(err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self { Self::Io(err) }
+}
+
+impl From<FormatError> for Error {
+    fn from(err: FormatError) -> Self { Self::Format(err) }
+}
+
+// Checks a length against the limit of the target integer type
+pub fn check_len<T: TryFrom<usize>>(len: usize, max: usize) -> Result<T> {
+    T::try_from(len).map_err(|_| Error::InputTooLarge { len, max })
+}
@@ -0,0 +1,277 @@
+use std::io::Write;
+use std::ops::Range;
+
+use crate::container::FormatError;
+use crate::decode::read_varint;
+use crate::error::{Error, Result};
+use crate::parser::write_varint;
+use crate::trie::Trie;
+
+// The grammar form of the dictionary (a straight-line program): every word is parsed with the shorter words
+// Parsing with the earlier words only would find next to nothing: later words are picked from the literals left
+// after the splits, so they hardly ever contain an earlier word, while earlier words often contain later ones
+// The ranking charges a candidate its parse with the words picked before it (see Grammar::cost), which keep their
+// index in the dictionary and are all shorter, so encode_dict finds that parse or a cheaper one: the charge is an
+// upper bound of the bytes the word is written with
+//
+// Layout: the number of words, then for each word the number of parts and the parts (all counts are LEB128 varints)
+// A part starts with a varint header, (j << 1) | 1 references the word j (which is always shorter),
+// len << 1 is followed by len raw bytes
+
+// The words picked so far, used to parse other words
+#[derive(Clone, Default, Debug)]
+pub struct Grammar {
+    trie: Trie
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Literals(Range<usize>), // Into the parsed bytes (or the dictionary section when decoding)
+    Word(usize)
+}
+
+fn varint_len(value: u64) -> usize {
+    (64 - (value | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+fn word_header(word: usize) -> u64 { ((word as u64) << 1) | 1 }
+
+// The expanded words take at most this many times the bytes of the section, words can double in length with
+// every part, so a few bytes of a hostile grammar would otherwise expand to any size
+// encode_dict refuses a dictionary that expands more, decode_dict rejects one
+const MAX_EXPANSION: usize = 1 << 16;
+
+// States of the parse, by the kind of the last part
+const LITERAL: usize = 0;
+const WORD: usize = 1;
+
+impl Grammar {
+    pub fn new() -> Self { Self { trie: Trie::new() } }
+
+    pub fn insert(&mut self, word: &[u8], index: usize) { self.trie.insert(word, index); }
+
+    // The cheapest parse of bytes in encoded bytes, also returns the cost
+    // The header of a literal run is counted as a single byte, which is exact for runs of up to 63 bytes
+    // A word never covers all of bytes, so a word can't reference itself (or a duplicate)
+    fn parse_parts(&self, bytes: &[u8]) -> (Vec<Part>, usize) {
+        let n = bytes.len();
+        // cost[state][i] is the cost of bytes[..i] ending with a part of that kind, the start counts as a word
+        let mut cost = [vec![usize::MAX; n + 1], vec![usize::MAX; n + 1]];
+        // back[state][i] is the state before the last part, the word it references (for WORD) and its length
+        let mut back = [vec![(0, 0, 0); n + 1], vec![(0, 0, 0); n + 1]];
+        cost[WORD][0] = 0;
+
+        for start in 0..n {
+            let (prev, best) = if cost[LITERAL][start] <= cost[WORD][start] { (LITERAL, cost[LITERAL][start]) }
+                               else                                        { (WORD, cost[WORD][start]) };
+
+            // Extending a run is cheaper than starting one
+            let extend = cost[LITERAL][start].saturating_add(1);
+            let open = cost[WORD][start].saturating_add(2);
+            let (literal_prev, literal_cost) = if extend <= open { (LITERAL, extend) } else { (WORD, open) };
+            if literal_cost < cost[LITERAL][start + 1] {
+                cost[LITERAL][start + 1] = literal_cost;
+                back[LITERAL][start + 1] = (literal_prev, 0, 1);
+            }
+
+            for (len, word) in self.trie.prefixes(&bytes[start..]) {
+                let end = start + len;
+                if start == 0 && end == n { break; }
+                let word_cost = best + varint_len(word_header(word));
+                if word_cost < cost[WORD][end] {
+                    cost[WORD][end] = word_cost;
+                    back[WORD][end] = (prev, word, len);
+                }
+            }
+        }
+
+        let mut state = if cost[LITERAL][n] < cost[WORD][n] { LITERAL } else { WORD };
+        let total = cost[state][n];
+        let mut parts = Vec::new();
+        let mut end = n;
+        while end > 0 {
+            let (prev, word, len) = back[state][end];
+            match (state, parts.last_mut()) {
+                (LITERAL, Some(Part::Literals(run))) if run.start == end => run.start -= 1,
+                (LITERAL, _) => parts.push(Part::Literals((end - 1)..end)),
+                _ => parts.push(Part::Word(word))
+            }
+            end -= len;
+            state = prev;
+        }
+
+        parts.reverse();
+        (parts, total)
+    }
+
+    // The bytes a word takes in the grammar section when parsed with the inserted words
+    pub fn cost(&self, bytes: &[u8]) -> usize {
+        let (parts, cost) = self.parse_parts(bytes);
+        cost + varint_len(parts.len() as u64)
+    }
+}
+
+pub fn encode_dict<W: Write>(words: &[Vec<u8>], writer: &mut W) -> Result<()> {
+    // Parse the words from the shortest, with a trie of the words that are strictly shorter
+    let mut by_len: Vec<usize> = (0..words.len()).collect();
+    by_len.sort_by_key(|&index| words[index].len());
+
+    let mut grammar = Grammar::new();
+    let mut parsed = vec![Vec::new(); words.len()];
+    for group in by_len.chunk_by(|&a, &b| words[a].len() == words[b].len()) {
+        for &index in group { parsed[index] = grammar.parse_parts(&words[index]).0; }
+        for &index in group { grammar.insert(&words[index], index); }
+    }
+
+    let mut out = Vec::new();
+    write_varint(words.len() as u64, &mut out);
+    for (word, parts) in words.iter().zip(parsed) {
+        write_varint(parts.len() as u64, &mut out);
+        for part in parts {
+            match part {
+                Part::Literals(run) => {
+                    write_varint((run.len() as u64) << 1, &mut out);
+                    out.extend_from_slice(&word[run]);
+                },
+                Part::Word(j) => write_varint(word_header(j), &mut out)
+            }
+        }
+    }
+
+    let total = words.iter().map(Vec::len).sum();
+    let max = out.len().saturating_mul(MAX_EXPANSION);
+    if total > max { return Err(Error::InputTooLarge { len: total, max }); }
+
+    Ok(writer.write_all(&out)?)
+}
+
+// Returns the expanded words and the number of bytes read, data is the dictionary section
+pub fn decode_dict(data: &[u8]) -> Result<(Vec<Vec<u8>>, usize)> {
+    let (count, mut pos) = read_varint(data, 0)?;
+    // Every word takes at least a byte, don't trust the count for the allocation
+    let mut rules: Vec<Vec<Part>> = Vec::with_capacity((count as usize).min(data.len()));
+
+    for _ in 0..count {
+        let (parts, next) = read_varint(data, pos)?;
+        pos = next;
+
+        let mut rule = Vec::with_capacity((parts as usize).min(data.len() - pos));
+        for _ in 0..parts {
+            let (header, next) = read_varint(data, pos)?;
+            pos = next;
+
+            if header & 1 == 1 {
+                let j = header >> 1;
+                if j >= count { return Err(FormatError::CorruptDictionary.into()); }
+                rule.push(Part::Word(j as usize));
+            } else {
+                let end = usize::try_from(header >> 1).ok()
+                    .and_then(|len| pos.checked_add(len))
+                    .filter(|&end| end <= data.len())
+                    .ok_or(FormatError::Truncated)?;
+                rule.push(Part::Literals(pos..end));
+                pos = end;
+            }
+        }
+        rules.push(rule);
+    }
+
+    Ok((expand(&rules, data, data.len().saturating_mul(MAX_EXPANSION))?, pos))
+}
+
+// Expands the rules depth first without recursion, a rule that (indirectly) references itself is corrupt
+// So is a dictionary of more than max_total bytes
+fn expand(rules: &[Vec<Part>], data: &[u8], max_total: usize) -> Result<Vec<Vec<u8>>> {
+    let mut words: Vec<Option<Vec<u8>>> = vec![None; rules.len()];
+    let mut pending = vec![false; rules.len()];
+    let mut stack = Vec::new();
+    let mut total = 0usize;
+
+    for root in 0..rules.len() {
+        stack.push(root);
+        while let Some(&index) = stack.last() {
+            if words[index].is_some() { stack.pop(); continue; }
+            pending[index] = true;
+
+            let missing = rules[index].iter().find_map(|part| match *part {
+                Part::Word(j) if words[j].is_none() => Some(j),
+                _ => None
+            });
+            if let Some(j) = missing {
+                if pending[j] { return Err(FormatError::CorruptDictionary.into()); }
+                stack.push(j);
+                continue;
+            }
+
+            let mut word = Vec::new();
+            for part in &rules[index] {
+                let bytes = match part {
+                    Part::Literals(run) => &data[run.clone()],
+                    Part::Word(j) => words[*j].as_deref().unwrap()
+                };
+                if total + word.len() + bytes.len() > max_total { return Err(FormatError::CorruptDictionary.into()); }
+                word.extend_from_slice(bytes);
+            }
+            total += word.len();
+            words[index] = Some(word);
+            pending[index] = false;
+            stack.pop();
+        }
+    }
+
+    Ok(words.into_iter().map(Option::unwrap).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Word 0 is two bytes, every other word is the previous one twice
+    fn doubling(words: u64) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_varint(words, &mut data);
+        data.extend([1, 2 << 1]);
+        data.extend_from_slice(b"ab");
+        for j in 1..words {
+            write_varint(2, &mut data);
+            for _ in 0..2 { write_varint(word_header(j as usize - 1), &mut data); }
+        }
+
+        data
+    }
+
+    #[test]
+    fn words_roundtrip() {
+        let words: Vec<Vec<u8>> = [&b"the"[..], b" the cat", b"at", b"the cat sat", b"\x00\xff"].iter().map(|word| word.to_vec()).collect();
+        let mut data = Vec::new();
+        encode_dict(&words, &mut data).unwrap();
+        assert_eq!(decode_dict(&data).unwrap(), (words, data.len()));
+
+        let (words, len) = decode_dict(&doubling(10)).unwrap();
+        assert_eq!((words[9].len(), len), (1024, doubling(10).len()));
+    }
+
+    #[test]
+    fn ranking_cost_bounds_the_section() {
+        // Picked longest first, then a word that only the later words parse
+        let words: Vec<Vec<u8>> = [&b"the cat sat on the mat"[..], b"the cat", b" on ", b"at", b"on the cat sat the mat"].iter().map(|word| word.to_vec()).collect();
+        let mut grammar = Grammar::new();
+        let mut cost = varint_len(words.len() as u64);
+        for (index, word) in words.iter().enumerate() {
+            cost += grammar.cost(word);
+            grammar.insert(word, index);
+        }
+
+        let mut data = Vec::new();
+        encode_dict(&words, &mut data).unwrap();
+        assert!(data.len() < cost, "{} bytes, charged {cost}", data.len());
+        assert!(grammar.cost(b"the cat sat on the mat!") < 10);
+    }
+
+    #[test]
+    fn hostile_grammar_is_rejected() {
+        // 2^64 bytes from a couple of hundred
+        let result = decode_dict(&doubling(64));
+        assert!(matches!(result, Err(Error::Format(FormatError::CorruptDictionary))));
+    }
+}
@@ -1,59 +1,137 @@
 use std::cmp::Ordering;
+use std::ops::Range;
+use std::thread;
 use std::time::Instant;
 
-use crate::bindings;
-use crate::entropy_ranking::{rank, update_model};
+use crate::alignment::Alignment;
+use crate::backend::{Backend, DefaultBackend};
+use crate::boundaries::Boundaries;
+use crate::container::DictEncoding;
+use crate::documents;
+use crate::error::{check_len, Result};
+use crate::entropy_ranking::update_model;
+use crate::grammar::Grammar;
+use crate::index::SaIndex;
 use crate::splitting::split;
 use crate::match_finder;
+use crate::ranking::RankingStrategy;
+use crate::records::Records;
+use crate::selection::{rank_matches, RankQueue, Selection};
+use crate::{BuildOptions, Options};
 
-pub struct DictIndex {
+// Tokens are u32 and the first 256 are the literals
+pub const MAX_WORDS: usize = u32::MAX as usize - 255;
+
+// About as many matches as generate produces per byte of text, to allocate them at once
+const MATCHES_PER_BYTE: f64 = 2.3;
+
+pub struct DictIndex<I: SaIndex> {
     pub buf:        Vec<u8>,
-    pub sa:         Vec<i32>,
-    pub offsets:    Vec<i32>,
+    pub sa:         Vec<I>,
+    pub offsets:    Vec<I>,
     pub model:      [f64; 256],
-    pub sym_counts: [f64; 256],
-    pub n: u32,
-    pub replacement_token: i32
+    pub byte_counts: Vec<[u32; 256]>, // Counts of the bytes of buf, see count_bytes
+    pub n: u64,
+    pub replacement_token: I,
+    pub grammar: Option<Grammar>, // The words picked so far, when the dictionary is stored as a grammar
+    pub doc_starts: Vec<usize>,   // Where each document starts in buf, see initialize_documents
+    pub boundaries: Boundaries,   // Where words may start and end
+    pub records: Records,         // Delimiters words never span
+    pub alignment: Alignment,     // Where occurrences start and how long words are in structured data
+    pub build: BuildOptions
+}
+
+pub fn initialize<I: SaIndex>(buf: Vec<u8>, options: &Options) -> Result<DictIndex<I>> {
+    check_len::<I>(buf.len(), I::MAX)?;
+    let sa = build_suffix_array(&buf, options)?;
+    Ok(with_suffix_array(buf, sa, vec![0], options))
+}
+
+// Same as initialize for several documents, the SA is generalized (see documents.rs) so no match spans two of them
+pub fn initialize_documents<I: SaIndex>(docs: &[&[u8]], options: &Options) -> Result<DictIndex<I>> {
+    if docs.len() <= 1 { return initialize(docs.first().map_or(Vec::new(), |doc| doc.to_vec()), options); }
+
+    let timer = Instant::now();
+    let (buf, doc_starts) = documents::concat(docs);
+    let sa = documents::suffix_array::<I, DefaultBackend>(&buf, &doc_starts)?;
+    if options.verbose { eprintln!("Built generalized SA of {} documents in {:?}", docs.len(), timer.elapsed()); }
+
+    Ok(with_suffix_array(buf, sa, doc_starts, options))
 }
 
-pub fn initialize(buf: Vec<u8>) -> DictIndex {
-    let len: u32 = buf.len().try_into().expect("Buffer must fit into u32 type!");
-    let sa = build_suffix_array(&buf);
+fn with_suffix_array<I: SaIndex>(buf: Vec<u8>, sa: Vec<I>, doc_starts: Vec<usize>, options: &Options) -> DictIndex<I> {
     let model = build_model(&buf);
+    let byte_counts = build_byte_counts(&buf);
     let offsets = build_offsets_array(buf.len());
 
-    DictIndex { n: len, buf, sa, offsets, model, sym_counts: [0f64; 256], replacement_token: -256 }
+    let mut dict_index = DictIndex {
+        n: buf.len() as u64, buf, sa, offsets, model, byte_counts, replacement_token: -I::from(256), grammar: None, doc_starts,
+        boundaries: Boundaries::default(), records: Records::default(), alignment: Alignment::default(), build: BuildOptions::default()
+    };
+    configure(&mut dict_index, options);
+
+    dict_index
+}
+
+// Takes the constraints and limits of the words and the dictionary encoding from options
+// initialize sets them for analyze and parse, build_dictionary sets them again from the options it's given
+fn configure<I: SaIndex>(dict_index: &mut DictIndex<I>, options: &Options) {
+    dict_index.grammar = (options.dict_encoding == DictEncoding::Grammar).then(Grammar::new);
+    dict_index.boundaries = options.boundaries;
+    dict_index.records = options.records;
+    dict_index.alignment = options.alignment;
+    dict_index.build = options.build;
 }
 
-pub fn build_dictionary(dict_index: &mut DictIndex) -> Vec<Word> {
+pub fn build_dictionary<I: SaIndex, R: RankingStrategy>(dict_index: &mut DictIndex<I>, options: &Options, strategy: &mut R) -> Result<Vec<Word>> {
     // The cast here is ok, because it's just an approximation we're making and the value may never become negative
-    let mut curr_matches = Vec::with_capacity((dict_index.buf.len() as f64 * 2.3) as usize);
+    let mut curr_matches = Vec::with_capacity((dict_index.buf.len() as f64 * MATCHES_PER_BYTE) as usize);
     let mut dict = Vec::with_capacity(128);
+    configure(dict_index, options);
 
     // Initialize with all the macthes
-    // match_finder::_static_analyze(lcp_array);
-    let lcp_array = build_lcp_array(&dict_index.buf, &dict_index.sa);
-    match_finder::generate(&mut curr_matches, lcp_array);
+    let lcp_array = build_lcp_array(dict_index, options)?;
+    match_finder::generate(&mut curr_matches, lcp_array, dict_index, options);
 
-    loop {
-        let best_word = curr_matches.iter_mut()
-            .filter(|m| m.is_valid)
-            .filter_map(|m| rank(m, dict_index))
-            .max_by(|x, y| cmp_f64(x.rank, y.rank));
+    let threads = match options.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n
+    };
+
+    strategy.init(dict_index);
+    let mut queue = match options.selection {
+        Selection::Lazy => Some(RankQueue::new(&mut curr_matches, dict_index, strategy, threads)),
+        Selection::Exhaustive => None
+    };
+
+    while dict.len() < dict_index.build.max_words {
+        let best_word = match queue.as_mut() {
+            Some(queue) => queue.pop_best(&mut curr_matches, dict_index, strategy),
+            None => rank_matches(&mut curr_matches, dict_index, strategy, threads)
+        };
 
         if best_word.is_none() { break; }
-        let best_word = best_word.unwrap();
 
         // best_word._print();
+        let mut best_word = best_word.unwrap();
+        let locations = split(&best_word, dict_index);
+        best_word.documents = documents::count(&locations, &dict_index.doc_starts);
         dict.push(best_word.clone());
-        split(&best_word, dict_index);
         update_model(&best_word, dict_index);
+        if let Some(grammar) = dict_index.grammar.as_mut() { grammar.insert(&dict_index.buf[best_word.get_range()], dict.len() - 1); }
+        strategy.update(&best_word, &locations, dict_index);
+        if let Some(queue) = queue.as_mut() { queue.advance(); }
     }
 
-    dict
+    Ok(dict)
+}
+
+pub fn analyze<I: SaIndex>(dict_index: &DictIndex<I>, options: &Options) -> Result<match_finder::Stats> {
+    let lcp_array = build_lcp_array(dict_index, options)?;
+    Ok(match_finder::static_analyze(lcp_array, dict_index))
 }
 
-fn cmp_f64(a: f64, b: f64) -> Ordering {
+pub fn cmp_f64(a: f64, b: f64) -> Ordering {
     let a_is_normal = a.is_normal();
     let b_is_normal = b.is_normal();
 
@@ -71,55 +149,69 @@ fn cmp_f64(a: f64, b: f64) -> Ordering {
     }
 }
 
-fn build_suffix_array(buf: &[u8]) -> Vec<i32> {
+fn build_suffix_array<I: SaIndex>(buf: &[u8], options: &Options) -> Result<Vec<I>> {
     let timer = Instant::now();
-    let len: i32 = buf.len().try_into().expect("Buffer must fit into i32 type to use libsais!");
-    let mut sa = vec![0; buf.len()];
+    check_len::<I>(buf.len(), I::MAX)?;
+    let sa = DefaultBackend::suffix_array(buf)?;
+    if options.verbose { eprintln!("Built SA in {:?}", timer.elapsed()); }
 
-    let code = unsafe { bindings::libsais(buf.as_ptr(), sa.as_mut_ptr(), len, 0, std::ptr::null_mut::<i32>()) };
-    assert!(code == 0);
-    assert!(sa.len() == buf.len());
-    println!("Built SA in {:?}", timer.elapsed());
-
-    sa
+    Ok(sa)
 }
 
-fn build_lcp_array(buf: &[u8], sa: &[i32]) -> Vec<i32> {
+fn build_lcp_array<I: SaIndex>(dict_index: &DictIndex<I>, options: &Options) -> Result<Vec<I>> {
     let timer = Instant::now();
-    let len: i32 = buf.len().try_into().expect("Buffer must fit into i32 type to use libsais!");
-    let mut plcp = vec![0; buf.len()];
-    let mut lcp = vec![0; buf.len()+1];
-
-    let code = unsafe { bindings::libsais_plcp(buf.as_ptr(), sa.as_ptr(), plcp.as_mut_ptr(), len) };
-    assert!(code == 0);
-    assert!(plcp.len() == buf.len());
+    let DictIndex { buf, sa, doc_starts, .. } = dict_index;
+    let lcp = if doc_starts.len() > 1 { documents::lcp_array(buf, sa, doc_starts) }
+              else                    { DefaultBackend::lcp_array(buf, sa)? };
+    if options.verbose { eprintln!("Built LCP in {:?}", timer.elapsed()); }
 
-    let code = unsafe { bindings::libsais_lcp(plcp.as_ptr(), sa.as_ptr(), lcp.as_mut_ptr(), len) };
-    assert!(code == 0);
-    // This is a bit of hacky magic because the previous implementation of an LCP array (using kasai's alg)
-    // produced an array ending with 0, while the libsais version has the extra 0 in the beginning
-    // This is ultimately based on where you assume the sentinel token to be placed
-    // As it is purely an implementation choice, I found this to be easier to adapt,
-    // rather than rewriting the matchfinder
-    lcp.remove(0);
-    assert!(lcp.len() == buf.len());
-    println!("Built LCP in {:?}", timer.elapsed());
-
-    lcp
+    Ok(lcp)
 }
 
-fn build_offsets_array(len: usize) -> Vec<i32> {
-    let mut vec = vec![0; len];
-    let max = i32::try_from(len).unwrap() - 1;
+// The length is already checked to fit into I by initialize
+fn build_offsets_array<I: SaIndex>(len: usize) -> Vec<I> {
+    let mut vec = vec![I::ZERO; len];
 
     // TODO: Does this get unrolled?
     vec.iter_mut()
         .enumerate()
-        .for_each(|(i, x)| *x = max - i32::try_from(i).unwrap());
+        .for_each(|(i, x)| *x = I::from_usize(len - 1 - i));
 
     vec
 }
 
+// The counts of every byte value in buf up to each multiple of COUNTS_STEP, so the counts of a long word take
+// O(COUNTS_STEP) (see count_bytes), they wrap, which keeps the difference of two exact for words below 4 GiB
+pub const COUNTS_STEP: usize = 4096;
+
+fn build_byte_counts(buf: &[u8]) -> Vec<[u32; 256]> {
+    let mut byte_counts = Vec::with_capacity(buf.len() / COUNTS_STEP + 1);
+    let mut counts = [0u32; 256];
+    byte_counts.push(counts);
+    for chunk in buf.chunks_exact(COUNTS_STEP) {
+        for &sym in chunk { counts[sym as usize] = counts[sym as usize].wrapping_add(1); }
+        byte_counts.push(counts);
+    }
+
+    byte_counts
+}
+
+// The counts of every byte value in buf[range]
+pub fn count_bytes<I: SaIndex>(dict_index: &DictIndex<I>, range: Range<usize>) -> [u32; 256] {
+    let mut counts = [0u32; 256];
+    let (first, last) = (range.start.div_ceil(COUNTS_STEP), range.end / COUNTS_STEP);
+    let (head, tail) = if first < last {
+        let (from, to) = (&dict_index.byte_counts[first], &dict_index.byte_counts[last]);
+        for sym in 0..256 { counts[sym] = to[sym].wrapping_sub(from[sym]); }
+        (range.start..(first * COUNTS_STEP), (last * COUNTS_STEP)..range.end)
+    } else {
+        (range, 0..0)
+    };
+
+    for &sym in dict_index.buf[head].iter().chain(&dict_index.buf[tail]) { counts[sym as usize] += 1; }
+    counts
+}
+
 fn build_model(buf: &[u8]) -> [f64; 256] {
     let mut model = [0f64; 256];
 
@@ -134,22 +226,44 @@ fn build_model(buf: &[u8]) -> [f64; 256] {
 pub struct Word {
     pub rank: f64,
     pub location: usize,
-    pub sa_index: u32,
-    pub sa_count: u32,
-    pub count: u32,
-    pub len: u16,
+    pub sa_index: usize,
+    pub sa_count: usize,
+    pub count: u64,
+    pub documents: u64, // The documents holding the replaced occurrences, set once the word is picked
+    pub len: u32,
 }
 
 impl Word {
     pub fn _print(&self) {
-        println!("word -> ({}, {}); c={}, r={}", self.location, self.len, self.count, self.rank);
+        println!("word -> ({}, {}); c={}, d={}, r={}", self.location, self.len, self.count, self.documents, self.rank);
     }
 
     pub fn get_range(&self) -> std::ops::Range<usize> {
-        (self.location as usize)..(self.location as usize + self.len as usize)
+        self.location..(self.location + self.len as usize)
     }
 
     pub fn get_sa_range(&self) -> std::ops::Range<usize> {
-        (self.sa_index as usize)..(self.sa_index as usize + self.sa_count as usize)
+        self.sa_index..(self.sa_index + self.sa_count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntropyRanking, Whole};
+
+    #[test]
+    fn build_dictionary_takes_its_options() {
+        let buf = "the cat sat on the mat, the cat ate the rat. ".repeat(40).into_bytes();
+        let mut dict_index = initialize::<i32>(buf, &Options::default()).unwrap();
+
+        let build = BuildOptions::new().min_len(3).max_words(4);
+        let options = Options { records: Records::new(b" ", Whole::None), build, ..Default::default() };
+        let dict = build_dictionary(&mut dict_index, &options, &mut EntropyRanking).unwrap();
+        assert_eq!(dict.len(), 4);
+        for word in &dict {
+            let bytes = &dict_index.buf[word.get_range()];
+            assert!(bytes.len() >= 3 && !bytes.contains(&b' '), "{:?}", String::from_utf8_lossy(bytes));
+        }
     }
 }
@@ -0,0 +1,109 @@
+use std::fmt::Debug;
+use std::ops::{Add, Neg, Sub};
+
+#[cfg(feature = "libsais")]
+use crate::bindings;
+#[cfg(feature = "libsais")]
+use crate::error::{Error, Result};
+
+// The integer type of the suffix array and the offsets array
+// Inputs up to i32::MAX bytes use i32 (as libsais does) to keep the index compact,
+// larger inputs switch to i64 (and the libsais64 functions)
+pub trait SaIndex: Copy + Ord + Default + Debug + Send + Sync
+    + Add<Output = Self> + Sub<Output = Self> + Neg<Output = Self>
+    + From<u16> + TryFrom<usize> + 'static
+{
+    const MAX: usize;
+    const ZERO: Self;
+
+    // Casts are safe because every value stored is a position or length in a buffer of at most MAX bytes
+    fn from_usize(value: usize) -> Self;
+    fn as_usize(self) -> usize;
+
+    // The libsais functions for this width, used by backend::Libsais
+    #[cfg(feature = "libsais")]
+    fn libsais(buf: &[u8], sa: &mut [Self]) -> Result<()>;
+    #[cfg(feature = "libsais")]
+    fn libsais_int(text: &mut [Self], sa: &mut [Self], k: usize) -> Result<()>;
+    #[cfg(feature = "libsais")]
+    fn libsais_plcp(buf: &[u8], sa: &[Self], plcp: &mut [Self]) -> Result<()>;
+    #[cfg(feature = "libsais")]
+    fn libsais_lcp(plcp: &[Self], sa: &[Self], lcp: &mut [Self]) -> Result<()>;
+}
+
+#[cfg(feature = "libsais")]
+fn libsais_result(function: &'static str, code: i64) -> Result<()> {
+    if code == 0 { Ok(()) } else { Err(Error::Libsais { function, code }) }
+}
+
+impl SaIndex for i32 {
+    const MAX: usize = i32::MAX as usize;
+    const ZERO: Self = 0;
+
+    fn from_usize(value: usize) -> Self { value as i32 }
+    fn as_usize(self) -> usize { self as usize }
+
+    #[cfg(feature = "libsais")]
+    fn libsais(buf: &[u8], sa: &mut [Self]) -> Result<()> {
+        let n = Self::from_usize(buf.len());
+        let code = unsafe { bindings::libsais(buf.as_ptr(), sa.as_mut_ptr(), n, 0, std::ptr::null_mut::<i32>()) };
+        libsais_result("libsais", code.into())
+    }
+
+    #[cfg(feature = "libsais")]
+    fn libsais_int(text: &mut [Self], sa: &mut [Self], k: usize) -> Result<()> {
+        let (n, k) = (Self::from_usize(text.len()), Self::from_usize(k));
+        let code = unsafe { bindings::libsais_int(text.as_mut_ptr(), sa.as_mut_ptr(), n, k, 0) };
+        libsais_result("libsais_int", code.into())
+    }
+
+    #[cfg(feature = "libsais")]
+    fn libsais_plcp(buf: &[u8], sa: &[Self], plcp: &mut [Self]) -> Result<()> {
+        let n = Self::from_usize(buf.len());
+        let code = unsafe { bindings::libsais_plcp(buf.as_ptr(), sa.as_ptr(), plcp.as_mut_ptr(), n) };
+        libsais_result("libsais_plcp", code.into())
+    }
+
+    #[cfg(feature = "libsais")]
+    fn libsais_lcp(plcp: &[Self], sa: &[Self], lcp: &mut [Self]) -> Result<()> {
+        let n = Self::from_usize(sa.len());
+        let code = unsafe { bindings::libsais_lcp(plcp.as_ptr(), sa.as_ptr(), lcp.as_mut_ptr(), n) };
+        libsais_result("libsais_lcp", code.into())
+    }
+}
+
+impl SaIndex for i64 {
+    const MAX: usize = i64::MAX as usize;
+    const ZERO: Self = 0;
+
+    fn from_usize(value: usize) -> Self { value as i64 }
+    fn as_usize(self) -> usize { self as usize }
+
+    #[cfg(feature = "libsais")]
+    fn libsais(buf: &[u8], sa: &mut [Self]) -> Result<()> {
+        let n = Self::from_usize(buf.len());
+        let code = unsafe { bindings::libsais64(buf.as_ptr(), sa.as_mut_ptr(), n, 0, std::ptr::null_mut::<i64>()) };
+        libsais_result("libsais64", code)
+    }
+
+    #[cfg(feature = "libsais")]
+    fn libsais_int(text: &mut [Self], sa: &mut [Self], k: usize) -> Result<()> {
+        let (n, k) = (Self::from_usize(text.len()), Self::from_usize(k));
+        let code = unsafe { bindings::libsais64_long(text.as_mut_ptr(), sa.as_mut_ptr(), n, k, 0) };
+        libsais_result("libsais64_long", code)
+    }
+
+    #[cfg(feature = "libsais")]
+    fn libsais_plcp(buf: &[u8], sa: &[Self], plcp: &mut [Self]) -> Result<()> {
+        let n = Self::from_usize(buf.len());
+        let code = unsafe { bindings::libsais64_plcp(buf.as_ptr(), sa.as_ptr(), plcp.as_mut_ptr(), n) };
+        libsais_result("libsais64_plcp", code)
+    }
+
+    #[cfg(feature = "libsais")]
+    fn libsais_lcp(plcp: &[Self], sa: &[Self], lcp: &mut [Self]) -> Result<()> {
+        let n = Self::from_usize(sa.len());
+        let code = unsafe { bindings::libsais64_lcp(plcp.as_ptr(), sa.as_ptr(), lcp.as_mut_ptr(), n) };
+        libsais_result("libsais64_lcp", code)
+    }
+}
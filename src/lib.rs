@@ -0,0 +1,293 @@
+use std::io::Write;
+
+mod incan74re;
+mod backend;
+mod alignment;
+mod boundaries;
+#[cfg(feature = "libsais")]
+mod bindings;
+#[cfg(feature = "pure-rust")]
+mod sais;
+mod documents;
+mod match_finder;
+mod ranking;
+mod entropy_ranking;
+mod order1_ranking;
+mod counting;
+mod splitting;
+mod selection;
+mod records;
+mod parser;
+mod decode;
+mod grammar;
+mod trie;
+mod encoder;
+mod range_coder;
+mod checksum;
+mod container;
+mod zstd_dict;
+mod deflate_dict;
+mod error;
+mod index;
+
+pub use crate::incan74re::{initialize, initialize_documents, build_dictionary, DictIndex, Word, MAX_WORDS};
+pub use crate::match_finder::Stats;
+pub use crate::encoder::{CostModel, Encoder};
+pub use crate::container::{DictEncoding, FormatError, TokenEncoding};
+pub use crate::deflate_dict::WINDOW as DEFLATE_WINDOW;
+pub use crate::error::{Error, Result};
+pub use crate::index::SaIndex;
+pub use crate::backend::{Backend, DefaultBackend};
+pub use crate::boundaries::Boundaries;
+pub use crate::records::{Records, Whole};
+pub use crate::alignment::Alignment;
+pub use crate::selection::Selection;
+pub use crate::ranking::{RankingStrategy, Candidate, ByteSavings, NetSavings, FixedWidth, CustomRanking};
+pub use crate::entropy_ranking::EntropyRanking;
+pub use crate::order1_ranking::Order1Entropy;
+#[cfg(feature = "libsais")]
+pub use crate::backend::Libsais;
+#[cfg(feature = "pure-rust")]
+pub use crate::sais::PureRust;
+
+#[derive(Clone, Default)]
+pub struct Options {
+    pub verbose:       bool,         // Print timings of the individual stages to stderr
+    pub threads:       usize,        // Threads for ranking the matches, 0 uses all available cores
+    pub selection:     Selection,    // How the best word is found on each iteration
+    pub dict_encoding: DictEncoding, // How the dictionary will be stored, the ranking counts its cost accordingly
+    pub boundaries:    Boundaries,   // Where words may start and end, e.g. Boundaries::words() for natural language text
+    pub records:       Records,      // Delimiters words never span, e.g. Records::new(b"\n", Whole::Lines(b'\n')) for logs
+    pub alignment:     Alignment,    // Aligned starts and lengths for structured data, e.g. Alignment::records(16)
+    pub build:         BuildOptions  // Limits of the words and the dictionary
+}
+
+// Tuning of initialize and build_dictionary, e.g. BuildOptions::new().min_len(4).max_words(4096)
+// The defaults pick every word that pays off, as the crate always did
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BuildOptions {
+    min_len:       u32,   // Shorter matches are never generated
+    max_len:       u32,   // Longer lengths of a match are never generated
+    min_count:     u64,   // Words with fewer (non-overlapping) occurrences are dropped
+    byte_overhead: f64,   // Bits a byte of a word takes in the dictionary, for the ranking
+    max_words:     usize, // build_dictionary stops after this many words
+    min_rank:      f64    // Words ranked at or below this are dropped
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self { min_len: 2, max_len: u32::MAX, min_count: 2, byte_overhead: 8f64, max_words: MAX_WORDS, min_rank: 0f64 }
+    }
+}
+
+impl BuildOptions {
+    pub fn new() -> Self { Self::default() }
+
+    // Words of a single byte save nothing, lengths below 2 are raised to 2
+    pub fn min_len(mut self, len: u32) -> Self { self.min_len = len.max(2); self }
+    pub fn max_len(mut self, len: u32) -> Self { self.max_len = len; self }
+    // A word has to occur at least twice, counts below 2 are raised to 2
+    pub fn min_count(mut self, count: u64) -> Self { self.min_count = count.max(2); self }
+    // The raw dictionary takes a byte per byte of a word and one more for its length, a compressed one takes less
+    pub fn byte_overhead(mut self, bits: f64) -> Self { self.byte_overhead = bits; self }
+    // Tokens are u32, more than MAX_WORDS words are lowered to MAX_WORDS
+    pub fn max_words(mut self, words: usize) -> Self { self.max_words = words.min(MAX_WORDS); self }
+    // Strategies may only lower their ranks as words are picked, so a dropped word never comes back
+    pub fn min_rank(mut self, rank: f64) -> Self { self.min_rank = rank; self }
+}
+
+// A trained dictionary, holding the bytes of each word
+// Word tokens are numbered in the order of the words, starting at 256
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Dictionary {
+    words:     Vec<Vec<u8>>,
+    documents: Vec<u64>    // The documents each word occurs in, only known when trained
+}
+
+impl Dictionary {
+    pub fn train(buf: &[u8], options: Options) -> Result<Self> {
+        Ok(encode(buf, options)?.dictionary)
+    }
+
+    pub fn train_ranked<R: RankingStrategy>(buf: &[u8], options: Options, strategy: R) -> Result<Self> {
+        Ok(encode_ranked(buf, options, strategy)?.dictionary)
+    }
+
+    // Trains a single dictionary shared by several documents (e.g. the sample files of a corpus), no word spans two of them
+    pub fn train_documents(docs: &[&[u8]], options: Options) -> Result<Self> {
+        Self::train_documents_ranked(docs, options, EntropyRanking)
+    }
+
+    pub fn train_documents_ranked<R: RankingStrategy>(docs: &[&[u8]], options: Options, strategy: R) -> Result<Self> {
+        let len: usize = docs.iter().map(|doc| doc.len()).sum();
+        if len + docs.len() + 256 <= i32::MAX as usize { train_documents_with::<i32, R>(docs, options, strategy) }
+        else                                          { train_documents_with::<i64, R>(docs, options, strategy) }
+    }
+
+    pub fn from_words(words: Vec<Vec<u8>>) -> Self { Self { words, documents: Vec::new() } }
+
+    fn from_index<I: SaIndex>(dict: &[Word], dict_index: &DictIndex<I>) -> Self {
+        let words = dict.iter().map(|word| dict_index.buf[word.get_range()].to_vec()).collect();
+        Self { words, documents: dict.iter().map(|word| word.documents).collect() }
+    }
+
+    pub fn words(&self) -> &[Vec<u8>] { &self.words }
+    // The number of documents each word occurs in (see train_documents), empty unless the dictionary was trained
+    pub fn documents(&self) -> &[u64] { &self.documents }
+    pub fn len(&self) -> usize { self.words.len() }
+    pub fn is_empty(&self) -> bool { self.words.is_empty() }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        parser::encode_dict(&self.words, writer)
+    }
+
+    // Returns the dictionary and the number of bytes it took
+    pub fn read(data: &[u8]) -> Result<(Self, usize)> {
+        let (words, size) = decode::decode_dict(data)?;
+        Ok((Self::from_words(words), size))
+    }
+}
+
+pub struct Encoded<'a> {
+    pub dictionary: Dictionary,
+    pub tokens: Vec<u32>,
+    pub original: &'a [u8],
+    // The words as build_dictionary picked them, with their rank and count, located in original
+    // (empty when encoded with a saved dictionary, see Encoder)
+    pub picks: Vec<Word>
+}
+
+impl Encoded<'_> {
+    // Writes the dictionary and tokens as a single container (see container.rs for the layout)
+    // The tokens are range coded and the dictionary raw, see write_with for the other encodings
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.write_with(TokenEncoding::default(), DictEncoding::default(), writer)
+    }
+
+    pub fn write_with<W: Write>(&self, encoding: TokenEncoding, dict_encoding: DictEncoding, writer: &mut W) -> Result<()> {
+        container::write(&self.dictionary.words, &self.tokens, self.original, encoding, dict_encoding, writer)
+    }
+
+    // Writes the dictionary in zstd's dictionary format, with entropy tables of the tokens (see zstd_dict.rs)
+    // Words are picked best first, so the first word ends up at the end of the content
+    // dict_id 0 derives the ID from the content
+    pub fn write_zstd_dict<W: Write>(&self, dict_id: u32, writer: &mut W) -> Result<()> {
+        let words: Vec<&[u8]> = self.dictionary.words.iter().map(Vec::as_slice).collect();
+        let order: Vec<usize> = (0..words.len()).rev().collect();
+        zstd_dict::write(&words, &order, &self.tokens, dict_id, writer)
+    }
+
+    // Writes the words as a raw DEFLATE preset dictionary of up to budget bytes, see write_deflate_dict
+    pub fn write_deflate_dict<W: Write>(&self, budget: usize, writer: &mut W) -> Result<u32> {
+        deflate_dict_with(&self.picks, self.original, budget, writer)
+    }
+
+    // Order-0 entropy of the tokens in bytes, what an ideal static coder would need for the token section
+    pub fn token_entropy(&self) -> f64 {
+        CostModel::Entropy.size(&self.tokens, 256 + self.dictionary.len())
+    }
+
+    // Replaces the tokens by the cheapest parse under the model, the split parse depends on the order the words
+    // were picked in and can't see that a different split would be cheaper
+    // Entropy costs come from the parse itself, so it's repeated while it gets smaller
+    pub fn optimize_parse(&mut self, model: CostModel) -> Result<ParseGain> {
+        let encoder = Encoder::new(self.dictionary.clone())?;
+        let alphabet = 256 + self.dictionary.len();
+        let before = model.size(&self.tokens, alphabet);
+        let mut size = before;
+
+        for _ in 0..PARSE_ROUNDS {
+            let tokens = encoder.tokenize_optimal(self.original, &model.costs(&self.tokens, alphabet));
+            let new_size = model.size(&tokens, alphabet);
+            if new_size >= size { break; }
+            self.tokens = tokens;
+            size = new_size;
+        }
+
+        Ok(ParseGain { before, after: size })
+    }
+}
+
+const PARSE_ROUNDS: usize = 4;
+
+// Sizes of the tokens in bytes under the cost model, before and after Encoded::optimize_parse
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ParseGain {
+    pub before: f64,
+    pub after:  f64
+}
+
+// Trains a dictionary on buf and parses buf with it
+pub fn encode(buf: &[u8], options: Options) -> Result<Encoded<'_>> {
+    encode_ranked(buf, options, EntropyRanking)
+}
+
+// Same as encode, but words are picked by the given strategy
+// Inputs that fit into i32 use the compact 32-bit index, larger ones the 64-bit one
+pub fn encode_ranked<R: RankingStrategy>(buf: &[u8], options: Options, strategy: R) -> Result<Encoded<'_>> {
+    if buf.len() <= i32::MAX as usize { encode_with::<i32, R>(buf, options, strategy) }
+    else                              { encode_with::<i64, R>(buf, options, strategy) }
+}
+
+fn encode_with<I: SaIndex, R: RankingStrategy>(buf: &[u8], options: Options, mut strategy: R) -> Result<Encoded<'_>> {
+    let mut dict_index = initialize::<I>(buf.to_vec(), &options)?;
+    let dict = build_dictionary(&mut dict_index, &options, &mut strategy)?;
+    let tokens = parser::parse(&dict, &mut dict_index);
+
+    Ok(Encoded { dictionary: Dictionary::from_index(&dict, &dict_index), tokens, original: buf, picks: dict })
+}
+
+fn train_documents_with<I: SaIndex, R: RankingStrategy>(docs: &[&[u8]], options: Options, mut strategy: R) -> Result<Dictionary> {
+    let mut dict_index = initialize_documents::<I>(docs, &options)?;
+    let dict = build_dictionary(&mut dict_index, &options, &mut strategy)?;
+
+    Ok(Dictionary::from_index(&dict, &dict_index))
+}
+
+// Writes the words of build_dictionary as a zstd dictionary, the highest ranked nearest to the end of the content
+// The entropy tables come from the training data as parsed by the words, dict_id 0 derives the ID from the content
+pub fn write_zstd_dict<I: SaIndex, W: Write>(dict: &[Word], dict_index: &DictIndex<I>, dict_id: u32, writer: &mut W) -> Result<()> {
+    let words: Vec<&[u8]> = dict.iter().map(|word| &dict_index.buf[word.get_range()]).collect();
+    let mut order: Vec<usize> = (0..dict.len()).collect();
+    order.sort_by(|&a, &b| incan74re::cmp_f64(dict[a].rank, dict[b].rank));
+    let tokens = parser::tokens(dict, dict_index);
+
+    zstd_dict::write(&words, &order, &tokens, dict_id, writer)
+}
+
+// Writes the words of build_dictionary as a raw preset dictionary for zlib's deflateSetDictionary, of up to budget
+// (usually DEFLATE_WINDOW) bytes, the words are chosen by rank * count with the best at the end
+// Returns the Adler-32 of the dictionary, the DICTID of zlib streams that use it
+pub fn write_deflate_dict<I: SaIndex, W: Write>(dict: &[Word], dict_index: &DictIndex<I>, budget: usize, writer: &mut W) -> Result<u32> {
+    deflate_dict_with(dict, &dict_index.buf, budget, writer)
+}
+
+fn deflate_dict_with<W: Write>(dict: &[Word], buf: &[u8], budget: usize, writer: &mut W) -> Result<u32> {
+    let words: Vec<&[u8]> = dict.iter().map(|word| &buf[word.get_range()]).collect();
+    let scores: Vec<f64> = dict.iter().map(|word| word.rank * word.count as f64).collect();
+    let preset = deflate_dict::build(&words, &scores, budget);
+    writer.write_all(&preset)?;
+
+    Ok(checksum::adler32(&preset))
+}
+
+pub fn decode(dictionary: &Dictionary, tokens: &[u32]) -> Result<Vec<u8>> {
+    decode::decode(&dictionary.words, tokens, usize::MAX)
+}
+
+// Validates a container written by Encoded::write and returns its dictionary and the original data
+pub fn read_container(data: &[u8]) -> Result<(Dictionary, Vec<u8>)> {
+    let (words, original) = container::read(data)?;
+    Ok((Dictionary::from_words(words), original))
+}
+
+// Analyzes the matches of buf without building a dictionary
+pub fn stats(buf: &[u8], options: Options) -> Result<Stats> {
+    if buf.len() <= i32::MAX as usize { stats_with::<i32>(buf, options) }
+    else                              { stats_with::<i64>(buf, options) }
+}
+
+fn stats_with<I: SaIndex>(buf: &[u8], options: Options) -> Result<Stats> {
+    let dict_index = initialize::<I>(buf.to_vec(), &options)?;
+    incan74re::analyze(&dict_index, &options)
+}
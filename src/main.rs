@@ -1,35 +1,350 @@
-use std::path::PathBuf;
-use std::{time::Instant, fs};
+use std::io::{self, Read, Write, BufWriter};
+use std::{env, fs, process, path::Path, str::FromStr, time::Instant};
 
-mod incan74re;
-mod bindings;
-mod match_finder;
-mod entropy_ranking;
-mod counting;
-mod splitting;
-mod parser;
+use incan74re::{Alignment, Boundaries, BuildOptions, ByteSavings, CostModel, DEFLATE_WINDOW, DictEncoding, Dictionary, Encoded, Encoder, EntropyRanking, FixedWidth, FormatError, NetSavings, Options, Order1Entropy, Records, Selection, TokenEncoding, Whole};
 
-fn main() {
-    let file = PathBuf::from("/data/calgary/book1");
-    let file_name = file.file_name().expect("Couldn't deduce filename").to_os_string();
-    let file_name =  file_name.to_str().expect("Invalid utf8 filename");
+const USAGE: &str = "\
+Usage: incan74re <command> [options] <input> [output]
+
+Commands:
+  train   Build a dictionary for <input> and write it to [output], for a directory <input> the dictionary
+          is shared by its files (no word spans two of them)
+  encode  Build a dictionary for <input> and write it with the parsed tokens as a container to [output]
+  decode  Rebuild the original data from an encoded <input> and write it to [output]
+  stats   Print match statistics for <input>
+
+Use - for stdin/stdout. The output defaults to stdout.
+
+Options:
+  -v, --verbose      Print timings to stderr
+  -t, --threads <n>  Threads for ranking matches (default: all cores)
+  -r, --ranking <r>  How words are ranked: entropy (default), order1, savings, net (savings less the dictionary)
+                     or fixed (16-bit tokens)
+  -d, --dict <path>  Encode with a dictionary saved by train instead of building one
+  -e, --encoding <e> How encode writes the tokens: range (default, range coded), packed (bit-packed),
+                     varint (frequency ranked LEB128), u16 or u32
+  --boundaries <b>   Words start and end only at these boundaries, a comma separated list of space
+                     (whitespace transitions), punct (punctuation transitions) and utf8 (codepoint starts),
+                     or words for all three
+  --delimiters <d>   Words never span these bytes (escapes \\n, \\r, \\t), e.g. \\n for logs or ,\\n for CSV
+  --whole <w>        Words may still be a whole field (fields) or a whole line (lines) with its delimiter
+  --align <a>        Words start at offset mod stride and are a multiple of unit bytes long: stride[:offset[:unit]],
+                     the unit defaults to the stride (e.g. 16 for arrays of 16-byte records)
+  --min-len <n>      Shortest word (default: 2)
+  --max-len <n>      Longest word (default: no limit)
+  --min-count <n>    Fewest occurrences of a word (default: 2)
+  --max-words <n>    Largest dictionary in words (default: no limit)
+  --min-rank <r>     Drop words ranked at or below this (default: 0)
+  --overhead <b>     Bits a byte of a word takes in the dictionary, for the ranking (default: 8)
+  --zstd             Write the dictionary of train in zstd's dictionary format (for zstd -D)
+  --deflate          Write the dictionary of train as a 32 KiB zlib preset dictionary, prints its Adler-32
+  --grammar          Store the dictionary of encode as a grammar, each word parsed with the shorter words
+  --optimal          Replace the parse of encode by the cheapest one for the encoding
+  --exhaustive       Re-rank every match on every iteration instead of lazily";
+
+enum Command { Train, Encode, Decode, Stats }
+
+enum Ranking { Entropy, Order1, Savings, Net, Fixed }
+
+struct Args {
+    command: Command,
+    input:   String,
+    output:  String,
+    verbose: bool,
+    threads: usize,
+    selection: Selection,
+    ranking: Ranking,
+    encoding: TokenEncoding,
+    dict_encoding: DictEncoding,
+    boundaries: Boundaries,
+    records: Records,
+    alignment: Alignment,
+    build: BuildOptions,
+    zstd: bool,
+    deflate: bool,
+    dict: Option<String>,
+    optimal: bool
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let (command, rest) = args.split_first().ok_or("Missing command")?;
+    let command = match command.as_str() {
+        "train"  => Command::Train,
+        "encode" => Command::Encode,
+        "decode" => Command::Decode,
+        "stats"  => Command::Stats,
+        _ => return Err(format!("Unknown command: {command}"))
+    };
+
+    let mut verbose = false;
+    let mut threads = 0;
+    let mut selection = Selection::Lazy;
+    let mut ranking = Ranking::Entropy;
+    let mut encoding = TokenEncoding::default();
+    let mut dict_encoding = DictEncoding::default();
+    let mut boundaries = Boundaries::default();
+    let mut delimiters = Vec::new();
+    let mut whole = Whole::None;
+    let mut alignment = Alignment::default();
+    let mut build = BuildOptions::new();
+    let mut zstd = false;
+    let mut deflate = false;
+    let mut dict = None;
+    let mut optimal = false;
+    let mut paths = Vec::with_capacity(2);
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-v" | "--verbose" => verbose = true,
+            "--exhaustive" => selection = Selection::Exhaustive,
+            "--grammar" => dict_encoding = DictEncoding::Grammar,
+            "--zstd" => zstd = true,
+            "--deflate" => deflate = true,
+            "--optimal" => optimal = true,
+            "-t" | "--threads" => {
+                let value = rest.next().ok_or(format!("Missing value for {arg}"))?;
+                threads = value.parse().map_err(|_| format!("Invalid thread count: {value}"))?;
+            },
+            "-r" | "--ranking" => {
+                let value = rest.next().ok_or(format!("Missing value for {arg}"))?;
+                ranking = match value.as_str() {
+                    "entropy" => Ranking::Entropy,
+                    "order1"  => Ranking::Order1,
+                    "savings" => Ranking::Savings,
+                    "net"     => Ranking::Net,
+                    "fixed"   => Ranking::Fixed,
+                    _ => return Err(format!("Unknown ranking: {value}"))
+                };
+            },
+            "--boundaries" => {
+                let value = rest.next().ok_or(format!("Missing value for {arg}"))?;
+                for class in value.split(',') {
+                    match class {
+                        "space" => boundaries.whitespace = true,
+                        "punct" => boundaries.punctuation = true,
+                        "utf8"  => boundaries.utf8 = true,
+                        "words" => boundaries = Boundaries::words(),
+                        _ => return Err(format!("Unknown boundary: {class}"))
+                    }
+                }
+            },
+            "--delimiters" => delimiters = parse_delimiters(rest.next().ok_or(format!("Missing value for {arg}"))?),
+            "--whole" => {
+                let value = rest.next().ok_or(format!("Missing value for {arg}"))?;
+                whole = match value.as_str() {
+                    "fields" => Whole::Fields,
+                    "lines"  => Whole::Lines(b'\n'),
+                    _ => return Err(format!("Unknown record: {value}"))
+                };
+            },
+            "--align" => alignment = parse_alignment(rest.next().ok_or(format!("Missing value for {arg}"))?)?,
+            "--min-len"   => build = build.min_len(parse_number(arg, rest.next())?),
+            "--max-len"   => build = build.max_len(parse_number(arg, rest.next())?),
+            "--min-count" => build = build.min_count(parse_number(arg, rest.next())?),
+            "--max-words" => build = build.max_words(parse_number(arg, rest.next())?),
+            "--min-rank"  => build = build.min_rank(parse_number(arg, rest.next())?),
+            "--overhead"  => build = build.byte_overhead(parse_number(arg, rest.next())?),
+            "-d" | "--dict" => dict = Some(rest.next().ok_or(format!("Missing value for {arg}"))?.clone()),
+            "-e" | "--encoding" => {
+                let value = rest.next().ok_or(format!("Missing value for {arg}"))?;
+                encoding = match value.as_str() {
+                    "range"  => TokenEncoding::Range,
+                    "packed" => TokenEncoding::Packed,
+                    "varint" => TokenEncoding::Varint,
+                    "u16"    => TokenEncoding::U16Be,
+                    "u32"    => TokenEncoding::U32Be,
+                    _ => return Err(format!("Unknown encoding: {value}"))
+                };
+            },
+            "-" => paths.push(arg.clone()),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {arg}")),
+            _ => paths.push(arg.clone())
+        }
+    }
+
+    let mut paths = paths.into_iter();
+    let input = paths.next().ok_or("Missing input path")?;
+    let output = paths.next().unwrap_or_else(|| String::from("-"));
+    if paths.next().is_some() { return Err(String::from("Too many arguments")); }
+    if whole == Whole::Fields && delimiters.is_empty() { return Err(String::from("--whole fields needs --delimiters")); }
+    let records = Records::new(&delimiters, whole);
+    if (zstd || deflate) && Path::new(&input).is_dir() { return Err(String::from("--zstd and --deflate train on a single file")); }
+
+    Ok(Args { command, input, output, verbose, threads, selection, ranking, encoding, dict_encoding, boundaries, records, alignment, build, zstd, deflate, dict, optimal })
+}
+
+// The bytes of a --delimiters value, with \n, \r, \t and \\ escaped
+fn parse_delimiters(value: &str) -> Vec<u8> {
+    let mut bytes = value.bytes();
+    let mut delimiters = Vec::with_capacity(value.len());
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' { delimiters.push(byte); continue; }
+        match bytes.next() {
+            Some(b'n') => delimiters.push(b'\n'),
+            Some(b'r') => delimiters.push(b'\r'),
+            Some(b't') => delimiters.push(b'\t'),
+            Some(other) => delimiters.push(other),
+            None => delimiters.push(byte)
+        }
+    }
+
+    delimiters
+}
+
+fn parse_number<T: FromStr>(arg: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or(format!("Missing value for {arg}"))?;
+    value.parse().map_err(|_| format!("Invalid value for {arg}: {value}"))
+}
+
+// stride[:offset[:unit]] of --align
+fn parse_alignment(value: &str) -> Result<Alignment, String> {
+    let parts = value.split(':').map(|part| part.parse::<usize>().ok()).collect::<Option<Vec<_>>>()
+        .filter(|parts| parts.len() <= 3 && parts[0] > 0 && parts.get(2) != Some(&0))
+        .ok_or(format!("Invalid alignment: {value}"))?;
+
+    Ok(Alignment { stride: parts[0], offset: parts.get(1).copied().unwrap_or(0), unit: parts.get(2).copied().unwrap_or(parts[0]) })
+}
 
-    println!("Building dict for: {:?}", file_name);
-    let buf = fs::read(file).expect("Couldn't read file into memory");
-    let mut index = incan74re::initialize(buf);
+fn read_input(path: &str) -> io::Result<Vec<u8>> {
+    if path != "-" { return fs::read(path); }
+
+    let mut buf = Vec::new();
+    io::stdin().lock().read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+fn create_output(path: &str) -> io::Result<Box<dyn Write>> {
+    if path == "-" { Ok(Box::new(BufWriter::new(io::stdout().lock()))) }
+    else           { Ok(Box::new(BufWriter::new(fs::File::create(path)?))) }
+}
+
+fn encode<'a>(buf: &'a [u8], options: Options, ranking: &Ranking) -> incan74re::Result<Encoded<'a>> {
+    match ranking {
+        Ranking::Entropy => incan74re::encode_ranked(buf, options, EntropyRanking),
+        Ranking::Order1  => incan74re::encode_ranked(buf, options, Order1Entropy::new()),
+        Ranking::Savings => incan74re::encode_ranked(buf, options, ByteSavings),
+        Ranking::Net     => incan74re::encode_ranked(buf, options, NetSavings),
+        Ranking::Fixed   => incan74re::encode_ranked(buf, options, FixedWidth::default())
+    }
+}
+
+// Every file of the directory is a document
+fn train_documents(args: &Args, options: Options) -> incan74re::Result<()> {
+    let timer = Instant::now();
+    let mut paths = fs::read_dir(&args.input)?.map(|entry| entry.map(|entry| entry.path())).collect::<io::Result<Vec<_>>>()?;
+    paths.retain(|path| path.is_file());
+    paths.sort();
+    let docs = paths.iter().map(fs::read).collect::<io::Result<Vec<_>>>()?;
+    let docs: Vec<&[u8]> = docs.iter().map(Vec::as_slice).collect();
+
+    let dictionary = match args.ranking {
+        Ranking::Entropy => Dictionary::train_documents_ranked(&docs, options, EntropyRanking),
+        Ranking::Order1  => Dictionary::train_documents_ranked(&docs, options, Order1Entropy::new()),
+        Ranking::Savings => Dictionary::train_documents_ranked(&docs, options, ByteSavings),
+        Ranking::Net     => Dictionary::train_documents_ranked(&docs, options, NetSavings),
+        Ranking::Fixed   => Dictionary::train_documents_ranked(&docs, options, FixedWidth::default())
+    }?;
+    if args.verbose {
+        eprintln!("Built dictionary of {} words over {} documents in {:?}", dictionary.len(), docs.len(), timer.elapsed());
+        for (word, count) in dictionary.words().iter().zip(dictionary.documents()) {
+            eprintln!("{count:>8} documents: {}", word.escape_ascii());
+        }
+    }
+
+    let mut writer = create_output(&args.output)?;
+    dictionary.write(&mut writer)?;
+    Ok(writer.flush()?)
+}
+
+fn load_encoder(path: &str) -> incan74re::Result<Encoder> {
+    let data = read_input(path)?;
+    let (dictionary, size) = Dictionary::read(&data)?;
+    if size != data.len() { return Err(FormatError::TrailingData(data.len() - size).into()); }
+    Encoder::new(dictionary)
+}
+
+fn run(args: Args) -> incan74re::Result<()> {
+    let options = Options { verbose: args.verbose, threads: args.threads, selection: args.selection, dict_encoding: args.dict_encoding,
+                            boundaries: args.boundaries, records: args.records, alignment: args.alignment, build: args.build };
+    if matches!(args.command, Command::Train) && Path::new(&args.input).is_dir() { return train_documents(&args, options); }
+    let buf = read_input(&args.input)?;
     let timer = Instant::now();
-    let dict = incan74re::build_dictionary(&mut index);
-    println!("Building dict took: {:?}", timer.elapsed());
-
-    // TODO: Move encode dict and decode dict to a new file
-    let bits_per_token = (dict.len() as f64).log2().ceil() as u32;
-    println!("Bits per token: {bits_per_token}");
-    parser::encode_dict(&dict, &index, &format!("dict-{}.bin", file_name));
-    parser::parse(&dict, &mut index, &format!("p-{}-{bits_per_token}.bin", file_name));
-
-    dbg!(dict.len());
-    if !dict.is_empty() {
-        dbg!(dict[0].location);
-        dbg!(dict[0].len);
+
+    match args.command {
+        Command::Train => {
+            let encoded = encode(&buf, options, &args.ranking)?;
+            if args.verbose { eprintln!("Built dictionary of {} words in {:?}", encoded.dictionary.len(), timer.elapsed()); }
+            let mut writer = create_output(&args.output)?;
+            if args.zstd {
+                encoded.write_zstd_dict(0, &mut writer)?;
+            } else if args.deflate {
+                let id = encoded.write_deflate_dict(DEFLATE_WINDOW, &mut writer)?;
+                eprintln!("Preset dictionary ID (Adler-32) {id:08x}");
+            } else {
+                encoded.dictionary.write(&mut writer)?;
+            }
+            Ok(writer.flush()?)
+        },
+        Command::Encode => {
+            let mut encoded = match &args.dict {
+                Some(path) => load_encoder(path)?.encode(&buf),
+                None => encode(&buf, options, &args.ranking)?
+            };
+            if args.optimal {
+                let model = CostModel::for_encoding(args.encoding, 256 + encoded.dictionary.len());
+                let gain = encoded.optimize_parse(model)?;
+                if args.verbose {
+                    let percent = if gain.before > 0f64 { 100f64 * (1f64 - gain.after / gain.before) } else { 0f64 };
+                    eprintln!("Optimal parse takes {:.0} bytes instead of {:.0} ({percent:.2}% less)", gain.after, gain.before);
+                }
+            }
+            let mut container = Vec::new();
+            encoded.write_with(args.encoding, args.dict_encoding, &mut container)?;
+            if args.verbose {
+                let built = if args.dict.is_some() { "Loaded" } else { "Built" };
+                eprintln!("{built} dictionary of {} words in {:?}", encoded.dictionary.len(), timer.elapsed());
+                eprintln!("Parsed {} bytes into {} tokens, order-0 entropy {:.0} bytes", buf.len(), encoded.tokens.len(), encoded.token_entropy());
+                eprintln!("Wrote {} bytes", container.len());
+            }
+            let mut writer = create_output(&args.output)?;
+            writer.write_all(&container)?;
+            Ok(writer.flush()?)
+        },
+        Command::Decode => {
+            let (_, decoded) = incan74re::read_container(&buf)?;
+            if args.verbose { eprintln!("Decoded {} bytes in {:?}", decoded.len(), timer.elapsed()); }
+            let mut writer = create_output(&args.output)?;
+            writer.write_all(&decoded)?;
+            Ok(writer.flush()?)
+        },
+        Command::Stats => {
+            let stats = incan74re::stats(&buf, options)?;
+            let mut writer = create_output(&args.output)?;
+            writeln!(writer, "total matches -> {}", stats.total_count)?;
+            writeln!(writer, "max sa count -> {}", stats.max_sa_count)?;
+            writeln!(writer, "max len -> {}", stats.max_len)?;
+            for (i, count) in stats.counts.iter().enumerate() {
+                writeln!(writer, "counts for len={} -> {count}", i+2)?;
+            }
+            let sum: u64 = stats.counts.iter().sum();
+            writeln!(writer, "counts for len>7 -> {}", stats.total_count - sum)?;
+            Ok(writer.flush()?)
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("{msg}\n\n{USAGE}");
+            process::exit(2);
+        }
+    };
+
+    if let Err(err) = run(args) {
+        eprintln!("Error: {err}");
+        process::exit(1);
     }
 }
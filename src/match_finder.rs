@@ -1,11 +1,31 @@
 use std::time::Instant;
 
-pub fn generate(matches: &mut Vec<Match>, lcp_array: Vec<i32>) {
+use crate::alignment;
+use crate::boundaries;
+use crate::documents;
+use crate::incan74re::DictIndex;
+use crate::index::SaIndex;
+use crate::records;
+use crate::Options;
+
+// Repeats longer than this take the long-match path:
+// their lengths are sampled geometrically (see match_lens) and only left-maximal ones are kept,
+// those that can be extended to the left (all occurrences follow the same byte) are covered by their extension
+// (see is_covered)
+// Without it a repeat of length L generates O(L^2) matches
+const LONG_LEN: u32 = 1024;
+
+// How far to the left is_covered looks for an extension that words may start at
+const MAX_EXTENSION: usize = 256;
+
+// Matches never span two documents of the index, the LCP array ends the common prefixes with the documents
+// Lengths that can't fit the boundaries, the records or the alignment of the index are skipped
+pub fn generate<I: SaIndex>(matches: &mut Vec<Match<I>>, lcp_array: Vec<I>, dict_index: &DictIndex<I>, options: &Options) {
     let timer = Instant::now();
-    let mut stack: Vec<MatchGen> = Vec::with_capacity(256);
+    let mut stack: Vec<MatchGen<I>> = Vec::with_capacity(256);
 
     for (index, lcp) in lcp_array.into_iter().enumerate() {
-        let lcp = u16::try_from(lcp).unwrap_or(u16::MAX);
+        let lcp = u32::try_from(lcp.as_usize()).unwrap_or(u32::MAX);
 
         // Push new matches
         if lcp > stack.last().map_or(1, |m| m.len) {
@@ -14,18 +34,19 @@ pub fn generate(matches: &mut Vec<Match>, lcp_array: Vec<i32>) {
 
         // Pop old matches
         while matches!(stack.last(), Some(m) if lcp < m.len) {
-            let mut min_len = stack.get(stack.len() - 2).map_or(2, |m| m.len + 1);
+            let mut min_len = stack.iter().nth_back(1).map_or(2, |m| m.len + 1);
             let mx = Match::new(index, stack.last().unwrap());
 
             if lcp >= min_len { stack.last_mut().unwrap().len = lcp; min_len = lcp + 1; }
             else              { stack.pop().unwrap(); }
 
-            for len in min_len..=mx.len { matches.push(Match::with_len(&mx, len)); }
+            if mx.len > LONG_LEN && is_covered(&mx, dict_index) { continue; }
+            for len in fitting_lens(&mx, min_len, dict_index) { matches.push(Match::with_len(&mx, len)); }
         }
     }
 
     assert!(stack.is_empty());
-    println!("Generated {} matches in: {:?}", matches.len(), timer.elapsed());
+    if options.verbose { eprintln!("Generated {} matches in: {:?}", matches.len(), timer.elapsed()); }
 }
 
 // MatchGen is a more lightweight struct that only holds the len and sa_index
@@ -33,52 +54,124 @@ pub fn generate(matches: &mut Vec<Match>, lcp_array: Vec<i32>) {
 // Since we always compute the sa_count field, we can not store it and save a couple of bytes
 
 // #[repr(packed(1))]
-struct MatchGen {
-    sa_index: u32,
-    len: u16
+struct MatchGen<I: SaIndex> {
+    sa_index: I,
+    len: u32
+}
+
+// Cast is safe because SA.len() <= I::MAX
+impl<I: SaIndex> MatchGen<I> {
+    fn new(sa_index: usize, len: u32) -> Self { Self { sa_index: I::from_usize(sa_index), len } }
 }
 
-// Cast is safe because SA.len() < u32::MAX
-impl MatchGen {
-    fn new(sa_index: usize, len: u16) -> Self { Self { sa_index: sa_index as u32, len } }
+// Every length up to LONG_LEN, then steps of 1/16th, always ending with max_len and never skipping the period
+fn match_lens(min_len: u32, max_len: u32, period: Option<u32>) -> impl Iterator<Item = u32> {
+    std::iter::successors(Some(min_len).filter(|&len| len <= max_len), move |&len| {
+        if len == max_len { return None; }
+        let next = if len < LONG_LEN { len + 1 } else { len + len / 16 };
+        let next = match period { Some(period) if len < period => next.min(period), _ => next };
+        Some(next.min(max_len))
+    })
+}
+
+// The longest length at which the occurrences of a long match don't overlap (the period of a repeat that follows
+// itself), if the match is longer, a repeat becomes a single word only at exactly this length
+fn period<I: SaIndex>(m: &Match<I>, dict_index: &DictIndex<I>) -> Option<u32> {
+    let mut locs: Vec<usize> = dict_index.sa[m.get_range()].iter().map(|&loc| loc.as_usize()).collect();
+    locs.sort_unstable();
+    let gap = locs.windows(2).map(|pair| pair[1] - pair[0]).min()?;
+    u32::try_from(gap).ok().filter(|&gap| gap < m.len)
+}
+
+// The lengths from min_len of the match within the limits of BuildOptions that fit the records and the alignment unit
+// and may fit the boundaries
+// Sampled lengths are rounded down to the unit
+fn fitting_lens<'a, I: SaIndex>(m: &Match<I>, min_len: u32, dict_index: &'a DictIndex<I>) -> impl Iterator<Item = u32> + 'a {
+    let loc = dict_index.sa[m.sa_index.as_usize()].as_usize();
+    let min_len = min_len.max(dict_index.build.min_len);
+    let (m_len, max_len) = (m.len as usize, m.len.min(dict_index.build.max_len) as usize);
+    let fit = records::fit(dict_index, loc, max_len);
+    let alignment = dict_index.alignment;
+
+    // Casts are safe, the lengths are at most m.len
+    let unit = u32::try_from(alignment.unit.max(1)).unwrap_or(u32::MAX);
+    let mut last = 0;
+    let period = if m.len > LONG_LEN { period(m, dict_index) } else { None };
+    let plain = match_lens(min_len, (max_len as u32).min(fit.plain as u32), period)
+        .map(move |len| len - len % unit)
+        .filter(move |&len| { let new = len >= min_len && len != last; last = len; new });
+    let whole = fit.whole.filter(|&len| alignment.fits_len(len)).map(|len| len as u32).filter(|&len| len >= min_len);
+
+    plain.chain(whole).filter(move |&len| boundaries::may_fit(dict_index, loc, len as usize, m_len))
+}
+
+// True if a longer match covers this one: all occurrences follow the same bytes (of the same document)
+// and a word may start before every occurrence a word of the match may start at, the extension holds the same
+// words only longer
+// Also true if no word may start at any occurrence, none of the lengths would fit
+// An extension over a delimiter holds no word, the match isn't covered
+// With a stride a word starts only at aligned positions, the extension is at least a stride long
+fn is_covered<I: SaIndex>(m: &Match<I>, dict_index: &DictIndex<I>) -> bool {
+    let DictIndex { sa, buf, doc_starts, .. } = dict_index;
+    let locs = &sa[m.get_range()];
+    let loc = locs[0].as_usize();
+    let reach = locs.iter().map(|&o| o.as_usize())
+        .map(|o| o - doc_starts[documents::document(doc_starts, o)]).min().unwrap_or(0);
+    let starts_word = |pos: usize| (!dict_index.boundaries.is_active() || boundaries::is_boundary(dict_index, pos))
+                                && alignment::starts(dict_index, pos);
+    if !locs.iter().any(|&o| starts_word(o.as_usize())) { return true; }
+
+    for back in 1..=reach.min(MAX_EXTENSION.max(dict_index.alignment.stride)) {
+        if locs.iter().any(|&o| buf[o.as_usize() - back] != buf[loc - back]) { return false; }
+        if dict_index.records.is_delimiter(buf[loc - back]) { return false; }
+        if locs.iter().map(|&o| o.as_usize()).all(|o| !starts_word(o) || starts_word(o - back)) { return true; }
+    }
+
+    false
 }
 
 #[derive(Clone)]
-pub struct Match {
+pub struct Match<I: SaIndex> {
     pub self_ref: bool,
     pub is_valid: bool,
-    pub sa_index: u32,
-    pub sa_count: u32,
-    pub len:      u16
+    pub sa_index: I,
+    pub sa_count: I,
+    pub len:      u32
 }
 
-// Cast is safe because SA.len() < u32::MAX
-impl Match {
-    fn new(index: usize, mg: &MatchGen) -> Self {
-        Self { self_ref: true, sa_index: mg.sa_index, sa_count: index as u32 - mg.sa_index + 1, len: mg.len, is_valid: true }
+// Cast is safe because SA.len() <= I::MAX
+impl<I: SaIndex> Match<I> {
+    fn new(index: usize, mg: &MatchGen<I>) -> Self {
+        Self { self_ref: true, sa_index: mg.sa_index, sa_count: I::from_usize(index + 1) - mg.sa_index, len: mg.len, is_valid: true }
     }
 
-    fn with_len(m: &Match, len: u16) -> Self {
+    fn with_len(m: &Match<I>, len: u32) -> Self {
         let mut clone = m.clone();
         clone.len = len;
         clone
     }
 
     pub fn get_range(&self) -> std::ops::Range<usize> {
-        self.sa_index as usize .. (self.sa_index + self.sa_count) as usize
+        self.sa_index.as_usize() .. (self.sa_index + self.sa_count).as_usize()
     }
 }
 
-pub fn _static_analyze(lcp_array: Vec<i32>) {
-    let mut stack: Vec<MatchGen> = Vec::with_capacity(256);
-    let mut max_sa_count = 0;
-    let mut max_len = 0;
-    let mut total_count = 0;
-    let mut counts = [0; 6];
+// Statistics over the matches generate would produce, without allocating them
+pub struct Stats {
+    pub total_count:  u64,
+    pub max_sa_count: usize,
+    pub max_len:      usize,
+    pub counts:       [u64; 6] // Matches with len = 2..=7
+}
+
+pub fn static_analyze<I: SaIndex>(lcp_array: Vec<I>, dict_index: &DictIndex<I>) -> Stats {
+    let mut stack: Vec<MatchGen<I>> = Vec::with_capacity(256);
+    let mut stats = Stats { total_count: 0, max_sa_count: 0, max_len: 0, counts: [0; 6] };
 
     for (index, lcp) in lcp_array.into_iter().enumerate() {
-        if lcp > max_len { max_len = lcp; }
-        let lcp = u16::try_from(lcp).unwrap_or(u16::MAX);
+        let lcp = lcp.as_usize();
+        if lcp > stats.max_len { stats.max_len = lcp; }
+        let lcp = u32::try_from(lcp).unwrap_or(u32::MAX);
 
         // Push new matches
         if lcp > stack.last().map_or(1, |m| m.len) {
@@ -87,29 +180,81 @@ pub fn _static_analyze(lcp_array: Vec<i32>) {
 
         // Pop old matches
         while matches!(stack.last(), Some(m) if lcp < m.len) {
-            let mut min_len = stack.get(stack.len() - 2).map_or(2, |m| m.len + 1);
+            let mut min_len = stack.iter().nth_back(1).map_or(2, |m| m.len + 1);
             let mx = Match::new(index, stack.last().unwrap());
 
             if lcp >= min_len { stack.last_mut().unwrap().len = lcp; min_len = lcp + 1; }
             else              { stack.pop().unwrap(); }
 
-            // Stats
-            for len in min_len..8 {
-                counts[(len - 2) as usize] += 1;
+            if mx.len > LONG_LEN && is_covered(&mx, dict_index) { continue; }
+            for len in fitting_lens(&mx, min_len, dict_index) {
+                if len <= 7 { stats.counts[(len - 2) as usize] += 1; }
+                stats.total_count += 1;
             }
-            total_count += i32::from(mx.len - min_len + 1);
-            if mx.sa_count > max_sa_count { max_sa_count = mx.sa_count; }
+            if mx.sa_count.as_usize() > stats.max_sa_count { stats.max_sa_count = mx.sa_count.as_usize(); }
         }
     }
 
     assert!(stack.is_empty());
-    dbg!(total_count);
-    dbg!(max_sa_count);
-    dbg!(max_len);
-    for (i, count) in counts.iter().enumerate() {
-        println!("counts for len={} -> {count}", i+2);
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Alignment, Boundaries, Options};
+
+    // Text of pseudo-random words repeated, every long match is covered by the one starting with the block
+    fn repeated_text() -> Vec<u8> {
+        let words = ["the ", "quick ", "brown, ", "fox ", "jumps. ", "over ", "lazy ", "dög ", "and\n", "a ", "cat "];
+        let mut seed = 1u32;
+        let mut block = Vec::new();
+        while block.len() < 20000 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            block.extend_from_slice(words[(seed >> 16) as usize % words.len()].as_bytes());
+        }
+
+        block.repeat(3)
+    }
+
+    #[test]
+    fn repeated_block_is_one_word() {
+        // Random bytes repeat only as a whole, longer than LONG_LEN and than the byte counts step of the index
+        let mut seed = 7u32;
+        let block: Vec<u8> = (0..10000).map(|_| { seed = seed.wrapping_mul(1103515245).wrapping_add(12345); (seed >> 16) as u8 }).collect();
+        let buf = block.repeat(3);
+
+        let encoded = crate::encode(&buf, Options::default()).unwrap();
+        let word = &encoded.picks[0];
+        assert_eq!((word.len as usize, word.count), (block.len(), 3));
+        assert_eq!(encoded.tokens.len(), 3);
+    }
+
+    #[test]
+    fn boundaries_never_add_matches() {
+        let buf = repeated_text();
+        let plain = crate::stats(&buf, Options::default()).unwrap().total_count;
+
+        let space = Boundaries { whitespace: true, ..Default::default() };
+        let utf8 = Boundaries { utf8: true, ..Default::default() };
+        for boundaries in [space, utf8, Boundaries::words()] {
+            let options = Options { boundaries, ..Default::default() };
+            let count = crate::stats(&buf, options).unwrap().total_count;
+            assert!(count <= plain, "{boundaries:?}: {count} matches, {plain} without boundaries");
+        }
+    }
+
+    #[test]
+    fn strides_keep_pruning_matches() {
+        let buf = repeated_text();
+        let plain = crate::stats(&buf, Options::default()).unwrap().total_count;
+
+        // The copies of the block aren't aligned alike, 16 doesn't divide its length
+        // Matches less than a stride after the left-maximal one have no aligned extension, a few more are kept
+        assert_ne!(buf.len() / 3 % 16, 0);
+        for alignment in [Alignment { stride: 16, offset: 0, unit: 1 }, Alignment { stride: 4, offset: 1, unit: 2 }] {
+            let options = Options { alignment, ..Default::default() };
+            let count = crate::stats(&buf, options).unwrap().total_count;
+            assert!(count <= plain + plain / 16, "{alignment:?}: {count} matches, {plain} without alignment");
+        }
     }
-    let sum: i32 = counts.iter().sum();
-    println!("counts for len>7 -> {}", total_count - sum);
-    std::process::exit(1);
 }
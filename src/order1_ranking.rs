@@ -0,0 +1,144 @@
+use crate::incan74re::{DictIndex, Word};
+use crate::index::SaIndex;
+use crate::ranking::{xlog2, Candidate, RankingStrategy};
+
+// Context 256 is the start of the text and any position after a word token
+// Symbol 256 is any word token, which word it is gets an order-0 cost of its own
+const CONTEXTS: usize = 257;
+const TOKEN: usize = 256;
+
+// Ranks by the gain in order-1 (previous symbol) conditional entropy of the text, closer to what a CM coder sees
+// The pairs inside a word are counted exactly, the two boundaries of each occurrence are estimated:
+// the first byte is turned into a token within the same (unknown) contexts, which is approximated by the columns,
+// and the symbol after the word moves from the context of the last byte to the token context
+// The statistics themselves are kept exact on every update
+#[derive(Clone, Default, Debug)]
+pub struct Order1Entropy {
+    pairs:     Vec<f64>, // [context * CONTEXTS + symbol]
+    rows:      Vec<f64>, // Symbols seen in each context
+    columns:   Vec<f64>, // Occurrences of each symbol in any context
+    row_costs: Vec<f64>, // Bits to code the symbols of each context
+    tokens:    f64       // Word tokens in the text
+}
+
+impl Order1Entropy {
+    pub fn new() -> Self { Self::default() }
+
+    fn add(&mut self, context: usize, symbol: usize, count: f64) {
+        self.pairs[context * CONTEXTS + symbol] += count;
+        self.rows[context] += count;
+        self.columns[symbol] += count;
+    }
+
+    fn update_row_costs(&mut self) {
+        for (context, cost) in self.row_costs.iter_mut().enumerate() {
+            let row = &self.pairs[(context * CONTEXTS)..((context + 1) * CONTEXTS)];
+            *cost = xlog2(self.rows[context]) - row.iter().map(|&c| xlog2(c)).sum::<f64>();
+        }
+    }
+
+    // Average bits per symbol in the context
+    fn row_entropy(&self, context: usize) -> f64 {
+        let total = self.rows[context];
+        if total <= 0f64 { 0f64 } else { self.row_costs[context] / total }
+    }
+}
+
+impl RankingStrategy for Order1Entropy {
+    // The internal pairs of the candidate
+    type Scratch = Vec<usize>;
+    fn scratch(&self) -> Vec<usize> { Vec::with_capacity(64) }
+
+    fn init<I: SaIndex>(&mut self, dict_index: &DictIndex<I>) {
+        self.pairs = vec![0f64; CONTEXTS * CONTEXTS];
+        self.rows = vec![0f64; CONTEXTS];
+        self.columns = vec![0f64; CONTEXTS];
+        self.row_costs = vec![0f64; CONTEXTS];
+        self.tokens = 0f64;
+
+        let mut context = TOKEN;
+        for &sym in &dict_index.buf {
+            self.add(context, usize::from(sym), 1f64);
+            context = usize::from(sym);
+        }
+        self.update_row_costs();
+    }
+
+    fn rank<I: SaIndex>(&self, candidate: &Candidate, _: &DictIndex<I>, pairs: &mut Vec<usize>) -> f64 {
+        let bytes = candidate.bytes;
+        let count = candidate.count as f64;
+        let first = usize::from(bytes[0]);
+        let last = usize::from(bytes[bytes.len() - 1]);
+
+        pairs.clear();
+        pairs.extend(bytes.windows(2).map(|w| usize::from(w[0]) * CONTEXTS + usize::from(w[1])));
+        pairs.sort_unstable();
+
+        // Change in bits, the pairs are sorted by context, so the rows are grouped
+        let mut delta = 0f64;
+        let mut i = 0;
+        while i < pairs.len() {
+            let context = pairs[i] / CONTEXTS;
+            let mut in_row = 0f64;
+            while i < pairs.len() && pairs[i] / CONTEXTS == context {
+                let pair = pairs[i];
+                let mut times = 0f64;
+                while i < pairs.len() && pairs[i] == pair { times += 1f64; i += 1; }
+
+                let c = self.pairs[pair];
+                delta -= xlog2(c - times * count) - xlog2(c);
+                in_row += times;
+            }
+
+            let total = self.rows[context];
+            delta += xlog2(total - in_row * count) - xlog2(total);
+        }
+
+        // The first byte becomes a token
+        let (col_first, col_token) = (self.columns[first], self.columns[TOKEN]);
+        delta -= xlog2(col_first - count) - xlog2(col_first);
+        delta -= xlog2(col_token + count) - xlog2(col_token);
+
+        // The symbol after the word is now coded after a token
+        delta += count * (self.row_entropy(TOKEN) - self.row_entropy(last));
+
+        // Which token it is
+        delta += xlog2(self.tokens + count) - xlog2(self.tokens) - xlog2(count);
+
+        -delta - candidate.overhead
+    }
+
+    // Replays the split on the pairs, a position was a literal before the split if it's one now or
+    // if it's inside one of the new locations
+    fn update<I: SaIndex>(&mut self, word: &Word, locations: &[usize], dict_index: &DictIndex<I>) {
+        let buf = &dict_index.buf;
+        let offsets = &dict_index.offsets;
+        let len = word.len as usize;
+
+        let is_new = |pos: usize| {
+            let next = locations.partition_point(|&loc| loc <= pos);
+            next > 0 && pos < locations[next - 1] + len
+        };
+        let was_literal = |pos: usize| offsets[pos] >= I::ZERO || is_new(pos);
+        let context_before = |pos: usize| if pos == 0 || !was_literal(pos - 1) { TOKEN } else { usize::from(buf[pos - 1]) };
+        let context_after  = |pos: usize| if pos == 0 || offsets[pos - 1] < I::ZERO { TOKEN } else { usize::from(buf[pos - 1]) };
+
+        for &loc in locations {
+            for (pos, &sym) in buf.iter().enumerate().skip(loc).take(len) {
+                self.add(context_before(pos), usize::from(sym), -1f64);
+            }
+            self.add(context_after(loc), TOKEN, 1f64);
+
+            // The symbol after the word, unless it's the next new location which was handled already
+            let next = loc + len;
+            if next >= buf.len() || is_new(next) { continue; }
+            // The previous byte was a literal, so a token here must start here
+            let symbol = if offsets[next] >= I::ZERO { usize::from(buf[next]) } else { TOKEN };
+            self.add(usize::from(buf[next - 1]), symbol, -1f64);
+            self.add(TOKEN, symbol, 1f64);
+        }
+
+        self.tokens += locations.len() as f64;
+        self.update_row_costs();
+    }
+}
@@ -1,61 +1,138 @@
-use std::fs::File;
-use std::io::{Write, BufWriter};
-use std::ops::Neg;
+use std::io::Write;
 
+use crate::container::TokenEncoding;
+use crate::error::{check_len, Error, Result};
 use crate::incan74re::{DictIndex, Word};
+use crate::index::SaIndex;
 
 // The format for the dictionary (of size n) (currently) is:
-// 2 bytes for dictionary.len() to encode n
-// n words with 2+word.len() bytes -> 2 bytes for len and x bytes for the word
+// 4 bytes for dictionary.len() to encode n
+// n words with 4+word.len() bytes -> 4 bytes for len and x bytes for the word
+// (version 1 containers used 2 bytes for len, see decode::decode_dict_v1)
 // The order of the words in the dictionary is not restrictive and can be changed when further compressing the dict
 
-// TODO: Propagate io errors up
-pub fn encode_dict(dict: &[Word], dict_index: &DictIndex, file_name: &str) {
-    let mut writer = BufWriter::new(File::create(file_name).unwrap());
-    writer.write_all(&u32::try_from(dict.len()).unwrap().to_be_bytes()).unwrap();
+pub fn encode_dict<W: Write>(words: &[Vec<u8>], writer: &mut W) -> Result<()> {
+    writer.write_all(&check_len::<u32>(words.len(), u32::MAX as usize)?.to_be_bytes())?;
 
-    dict.iter()
-        .map(|word| {
-            let mut data = vec![0u8; word.len as usize + 2];
-            data[..2].copy_from_slice(&(word.len as u16).to_be_bytes());
-            data[2..].copy_from_slice(&dict_index.buf[word.get_range()]);
-
-            data
-        })
-        .for_each(|word_data| writer.write_all(&word_data).unwrap());
+    for word in words {
+        writer.write_all(&check_len::<u32>(word.len(), u32::MAX as usize)?.to_be_bytes())?;
+        writer.write_all(word)?;
+    }
 
-    writer.flush().unwrap();
+    Ok(())
 }
 
-// Creates a u16 array (big-endian order) of word indexes and writes it to a file
+// Creates a u32 array of word indexes
 // Uses the offsets array from the dictionary computing phase for O(n) parsing
 // Indexes in the range [0 .. 255] are leftover uncovered raw literals
 // Indexes in the range [256 .. 256 + dict.len()] are dictionary words
 // Words can be decoded as dict[index-256], while literals as (index as u8)
 
-// Offset array mapping to words, mapping to parsed u16:
+// Offset array mapping to words, mapping to parsed u32:
 // -0     -> lit 0x00    -> 0
 // -1     -> lit 0x01    -> 1
 // -255   -> lit 0xff    -> 255
 // -256   -> dict[0]     -> 256
 // -257   -> dict[1]     -> 257
-// -65535 -> dict[65279] -> 65535 (u16::MAX)
-pub fn parse(dict: &[Word], dict_index: &mut DictIndex, file_name: &str) {
-    let mut writer = BufWriter::new(File::create(file_name).unwrap());
-
+// -65535 -> dict[65279] -> 65535 (the last token of U16Be)
+// build_dictionary stops at incan74re::MAX_WORDS, so every token fits into u32
+pub fn parse<I: SaIndex>(dict: &[Word], dict_index: &mut DictIndex<I>) -> Vec<u32> {
     // Cover with raw literals
     for (loc, x) in &mut dict_index.offsets.iter_mut().enumerate() {
-        if *x >= 0 { *x = i32::from(dict_index.buf[loc]).neg(); }
+        if *x >= I::ZERO { *x = I::from(u16::from(dict_index.buf[loc])).neg(); }
     }
 
+    tokens(dict, dict_index)
+}
+
+// Same as parse, without covering the literals in the offsets array, positions left uncovered are literals
+pub fn tokens<I: SaIndex>(dict: &[Word], dict_index: &DictIndex<I>) -> Vec<u32> {
+    let mut tokens = Vec::with_capacity(dict_index.offsets.len());
+
     let mut idx = 0;
     while idx < dict_index.offsets.len() {
-        let token = dict_index.offsets[idx].neg() as usize;
+        let offset = dict_index.offsets[idx];
+        let token = if offset >= I::ZERO { usize::from(dict_index.buf[idx]) } else { offset.neg().as_usize() };
+
+        tokens.push(token as u32);
+
+        idx += if token >= 256 { dict[token-256].len as usize } else { 1 };
+    }
+
+    tokens
+}
+
+// Writes the tokens in big-endian order, only dictionaries of up to 65280 words fit
+pub fn write_tokens<W: Write>(tokens: &[u32], writer: &mut W) -> Result<()> {
+    for &token in tokens {
+        let token = u16::try_from(token).map_err(|_| Error::TokenOverflow { token, encoding: TokenEncoding::U16Be })?;
+        writer.write_all(&token.to_be_bytes())?;
+    }
+
+    Ok(())
+}
 
-        writer.write_all(&(token as u16).to_be_bytes()).unwrap();
+pub fn write_tokens_u32<W: Write>(tokens: &[u32], writer: &mut W) -> Result<()> {
+    for token in tokens {
+        writer.write_all(&token.to_be_bytes())?;
+    }
+
+    Ok(())
+}
+
+// Bits needed for the largest token of the alphabet, at least 8 as there are always 256 literals
+pub fn bits_per_token(alphabet: usize) -> u32 {
+    usize::BITS - (alphabet - 1).leading_zeros()
+}
+
+// Packs the tokens with bits_per_token bits each, msb first, the last byte is padded with zeroes
+// The count isn't stored, the padding is shorter than a token
+pub fn write_tokens_packed<W: Write>(tokens: &[u32], alphabet: usize, writer: &mut W) -> Result<()> {
+    let width = bits_per_token(alphabet);
+    let mut packed = Vec::with_capacity((tokens.len() * width as usize).div_ceil(8));
+    let mut acc = 0u64;
+    let mut bits = 0;
 
-        idx += if token >= 256 { usize::from(dict[token-256].len) } else { 1 };
+    for &token in tokens {
+        acc = (acc << width) | u64::from(token);
+        bits += width;
+        while bits >= 8 {
+            bits -= 8;
+            packed.push((acc >> bits) as u8);
+        }
+        acc &= (1 << bits) - 1;
     }
+    if bits > 0 { packed.push((acc << (8 - bits)) as u8); }
+
+    Ok(writer.write_all(&packed)?)
+}
 
-    writer.flush().unwrap();
+// LEB128 varints of the token ranks, the most frequent token has rank 0 and takes a single byte
+// Layout: number of distinct tokens, the distinct tokens by rank, then the rank of each token (all varints)
+// The tokens must be smaller than alphabet
+pub fn write_tokens_varint<W: Write>(tokens: &[u32], alphabet: usize, writer: &mut W) -> Result<()> {
+    let mut counts = vec![0u64; alphabet];
+    for &token in tokens { counts[token as usize] += 1; }
+
+    // Ties go to the smaller token to keep the output deterministic
+    let mut by_rank: Vec<usize> = (0..alphabet).filter(|&token| counts[token] > 0).collect();
+    by_rank.sort_by(|&a, &b| counts[b].cmp(&counts[a]).then(a.cmp(&b)));
+
+    let mut ranks = vec![0u64; alphabet];
+    for (rank, &token) in by_rank.iter().enumerate() { ranks[token] = rank as u64; }
+
+    let mut out = Vec::with_capacity(tokens.len() + 5 * by_rank.len() + 5);
+    write_varint(by_rank.len() as u64, &mut out);
+    for &token in &by_rank { write_varint(token as u64, &mut out); }
+    for &token in tokens { write_varint(ranks[token as usize], &mut out); }
+
+    Ok(writer.write_all(&out)?)
+}
+
+pub fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
 }
@@ -0,0 +1,302 @@
+use crate::container::FormatError;
+use crate::error::Result;
+
+// Adaptive order-0 range coder for the token stream, the alphabet is 256 literals + the dictionary words
+// The coder is the carry-propagating one of LZMA (32-bit range, 33-bit low), the model a Fenwick tree of frequencies
+//
+// The precision of the coder limits a model to DIRECT symbols, larger alphabets code the tokens from ESCAPE on
+// as ESCAPE, followed by (token - ESCAPE) >> 16 in a second model and the low 16 bits uniformly
+
+const TOP: u32 = 1 << 24;
+const INCREMENT: u32 = 32;
+const DIRECT: usize = 1 << 16;
+const ESCAPE: usize = DIRECT - 1;
+const LOW_BITS: u32 = 16;
+
+// Cumulative frequencies over the alphabet
+struct Model {
+    tree: Vec<u32>, // Fenwick tree, 1-based
+    freqs: Vec<u32>,
+    total: u32,
+    limit: u32 // Frequencies are halved once the total would reach this
+}
+
+impl Model {
+    fn new(alphabet: usize) -> Self {
+        // Every symbol keeps a frequency of at least 1, leave room for the adaptation
+        let limit = (alphabet.next_power_of_two() * 2).max(1 << 16) as u32;
+        let mut model = Self { tree: vec![0; alphabet + 1], freqs: vec![1; alphabet], total: 0, limit };
+        model.rebuild();
+        model
+    }
+
+    fn rebuild(&mut self) {
+        self.tree.fill(0);
+        for (i, &freq) in self.freqs.iter().enumerate() {
+            let mut j = i + 1;
+            while j < self.tree.len() {
+                self.tree[j] += freq;
+                j += j & j.wrapping_neg();
+            }
+        }
+        self.total = self.freqs.iter().sum();
+    }
+
+    // Sum of the frequencies of the symbols before sym
+    fn cumulative(&self, sym: usize) -> u32 {
+        let mut sum = 0;
+        let mut i = sym;
+        while i > 0 {
+            sum += self.tree[i];
+            i &= i - 1;
+        }
+        sum
+    }
+
+    // The symbol whose range contains value, with its cumulative frequency
+    fn find(&self, mut value: u32) -> (usize, u32) {
+        let mut pos = 0;
+        let mut step = (self.tree.len() - 1).next_power_of_two();
+        let mut cumulative = 0;
+        while step > 0 {
+            let next = pos + step;
+            if next < self.tree.len() && self.tree[next] <= value {
+                pos = next;
+                value -= self.tree[next];
+                cumulative += self.tree[next];
+            }
+            step >>= 1;
+        }
+        (pos, cumulative)
+    }
+
+    fn update(&mut self, sym: usize) {
+        if self.total + INCREMENT >= self.limit {
+            self.freqs.iter_mut().for_each(|freq| *freq = freq.div_ceil(2));
+            self.rebuild();
+        }
+
+        self.freqs[sym] += INCREMENT;
+        self.total += INCREMENT;
+        let mut j = sym + 1;
+        while j < self.tree.len() {
+            self.tree[j] += INCREMENT;
+            j += j & j.wrapping_neg();
+        }
+    }
+}
+
+struct Encoder {
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: u64,
+    out: Vec<u8>
+}
+
+impl Encoder {
+    fn new() -> Self { Self { low: 0, range: u32::MAX, cache: 0, cache_size: 1, out: Vec::new() } }
+
+    fn encode(&mut self, cumulative: u32, freq: u32, total: u32) {
+        let r = self.range / total;
+        self.low += u64::from(r) * u64::from(cumulative);
+        self.range = r * freq;
+        while self.range < TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    // Holds back 0xff bytes until it's known whether a carry reaches them
+    fn shift_low(&mut self) {
+        if self.low < 0xff00_0000 || self.low >= 1 << 32 {
+            let carry = (self.low >> 32) as u8;
+            self.out.push(self.cache.wrapping_add(carry));
+            for _ in 1..self.cache_size { self.out.push(0xffu8.wrapping_add(carry)); }
+            self.cache = (self.low >> 24) as u8;
+            self.cache_size = 0;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00ff_ffff) << 8;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 { self.shift_low(); }
+        self.out
+    }
+}
+
+struct Decoder<'a> {
+    code: u32,
+    range: u32,
+    r: u32, // range / total of the symbol being decoded
+    data: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Decoder<'a> {
+    fn new(data: &'a [u8]) -> Result<Self> {
+        let mut decoder = Self { code: 0, range: u32::MAX, r: 0, data, pos: 0 };
+        for _ in 0..5 { decoder.code = (decoder.code << 8) | u32::from(decoder.next_byte()?); }
+        Ok(decoder)
+    }
+
+    fn next_byte(&mut self) -> Result<u8> {
+        let byte = *self.data.get(self.pos).ok_or(FormatError::Truncated)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    // The cumulative frequency the code points at
+    fn target(&mut self, total: u32) -> u32 {
+        self.r = self.range / total;
+        (self.code / self.r).min(total - 1)
+    }
+
+    fn consume(&mut self, cumulative: u32, freq: u32) -> Result<()> {
+        self.code -= self.r * cumulative;
+        self.range = self.r * freq;
+        while self.range < TOP {
+            self.range <<= 8;
+            self.code = (self.code << 8) | u32::from(self.next_byte()?);
+        }
+
+        Ok(())
+    }
+
+    fn decode(&mut self, model: &Model) -> Result<usize> {
+        let value = self.target(model.total);
+        let (sym, cumulative) = model.find(value);
+        self.consume(cumulative, model.freqs[sym])?;
+
+        Ok(sym)
+    }
+
+    fn decode_uniform(&mut self, total: u32) -> Result<u32> {
+        let value = self.target(total);
+        self.consume(value, 1)?;
+
+        Ok(value)
+    }
+}
+
+// The model of the tokens and, for alphabets over DIRECT symbols, of the high part of the escaped ones
+struct Models {
+    main: Model,
+    high: Option<Model>
+}
+
+impl Models {
+    fn new(alphabet: usize) -> Self {
+        if alphabet <= DIRECT { return Self { main: Model::new(alphabet), high: None }; }
+        Self { main: Model::new(DIRECT), high: Some(Model::new(((alphabet - 1 - ESCAPE) >> LOW_BITS) + 1)) }
+    }
+}
+
+// The tokens must be smaller than alphabet
+pub fn encode(tokens: &[u32], alphabet: usize) -> Vec<u8> {
+    let mut models = Models::new(alphabet);
+    let mut encoder = Encoder::new();
+
+    for &token in tokens {
+        let sym = token as usize;
+        let main_sym = if models.high.is_some() { sym.min(ESCAPE) } else { sym };
+        let main = &mut models.main;
+        encoder.encode(main.cumulative(main_sym), main.freqs[main_sym], main.total);
+        main.update(main_sym);
+
+        if let Some(high) = models.high.as_mut().filter(|_| main_sym == ESCAPE) {
+            let rest = sym - ESCAPE;
+            let high_sym = rest >> LOW_BITS;
+            encoder.encode(high.cumulative(high_sym), high.freqs[high_sym], high.total);
+            high.update(high_sym);
+            encoder.encode((rest & (DIRECT - 1)) as u32, 1, DIRECT as u32);
+        }
+    }
+
+    encoder.finish()
+}
+
+pub fn decode(data: &[u8], count: usize, alphabet: usize) -> Result<Vec<u32>> {
+    let mut models = Models::new(alphabet);
+    let mut decoder = Decoder::new(data)?;
+    // Don't trust the count for the allocation, a likely token takes a lot less than a byte
+    let mut tokens = Vec::with_capacity(count.min(1 << 24));
+
+    for _ in 0..count {
+        let mut sym = decoder.decode(&models.main)?;
+        models.main.update(sym);
+
+        if let Some(high) = models.high.as_mut().filter(|_| sym == ESCAPE) {
+            let high_sym = decoder.decode(high)?;
+            high.update(high_sym);
+            let low = decoder.decode_uniform(DIRECT as u32)? as usize;
+            sym = ESCAPE + (high_sym << LOW_BITS) + low;
+        }
+
+        tokens.push(u32::try_from(sym).map_err(|_| FormatError::InvalidToken(sym))?);
+    }
+
+    if decoder.pos != data.len() { return Err(FormatError::TrailingData(data.len() - decoder.pos).into()); }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(tokens: &[u32], alphabet: usize) -> Vec<u8> {
+        let data = encode(tokens, alphabet);
+        assert_eq!(decode(&data, tokens.len(), alphabet).unwrap(), tokens, "alphabet {alphabet}");
+        data
+    }
+
+    #[test]
+    fn fenwick_tree_matches_the_frequencies() {
+        // Enough updates to halve the frequencies a few times
+        let mut model = Model::new(300);
+        for i in 0..20000usize { model.update((i * i + i / 3) % 300 % (1 + i % 17)); }
+
+        for sym in 0..300 {
+            let cumulative: u32 = model.freqs[..sym].iter().sum();
+            assert_eq!(model.cumulative(sym), cumulative);
+            assert_eq!(model.find(cumulative), (sym, cumulative));
+            assert_eq!(model.find(cumulative + model.freqs[sym] - 1), (sym, cumulative));
+        }
+        assert_eq!(model.total, model.freqs.iter().sum());
+    }
+
+    #[test]
+    fn empty_and_single_symbol_streams() {
+        assert_eq!(roundtrip(&[], 256).len(), 5);
+        assert_eq!(roundtrip(&[], 1 << 20).len(), 5);
+
+        // The model adapts, a long run of one token takes a few bytes
+        assert!(roundtrip(&[97; 100000], 256).len() < 200);
+        roundtrip(&[0], 1);
+    }
+
+    #[test]
+    fn skewed_distributions_compress() {
+        // 99% of one token and 1% of a thousand others, about 0.2 bits a token (2.3 KB) in a static model
+        let mut seed = 5u32;
+        let tokens: Vec<u32> = (0..100000).map(|_| {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            if (seed >> 16).is_multiple_of(100) { 256 + (seed >> 8) % 1000 } else { 32 }
+        }).collect();
+
+        let len = roundtrip(&tokens, 256 + 1000).len();
+        assert!(len < 3000, "{len} bytes");
+    }
+
+    #[test]
+    fn escaped_alphabets_roundtrip() {
+        // The tokens around ESCAPE and the high models, past DIRECT and u16
+        let alphabet = 256 + 300000;
+        let mut tokens: Vec<u32> = vec![0, ESCAPE as u32 - 1, ESCAPE as u32, DIRECT as u32, (alphabet - 1) as u32, 70000, 70000, 70000];
+        tokens.extend((0..5000).map(|i| (i * 7919 % alphabet) as u32));
+        roundtrip(&tokens, alphabet);
+        roundtrip(&[ESCAPE as u32; 1000], DIRECT + 1);
+        roundtrip(&[ESCAPE as u32 - 1; 1000], DIRECT);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::counting::count;
+use crate::incan74re::{DictIndex, Word};
+use crate::index::SaIndex;
+use crate::match_finder::Match;
+
+// A match with its non-overlapping count, as seen by the ranking strategies
+pub struct Candidate<'a> {
+    pub bytes: &'a [u8],
+    pub location: usize,
+    pub count: u64,
+    pub n: u64,        // Symbols left in the text (bytes and words already picked)
+    pub overhead: f64  // Bits the word takes in the dictionary
+}
+
+// x * log2(x), 0 for a count of 0 (a word taking every occurrence of a symbol)
+pub fn xlog2(x: f64) -> f64 {
+    if x <= 0f64 { 0f64 } else { x * x.log2() }
+}
+
+// Decides how much a candidate is worth as a word, the best candidate is picked on each iteration
+// Candidates with a rank <= the min_rank of BuildOptions (0 by default) or NaN are dropped for good, so a rank should only shrink as words are picked
+pub trait RankingStrategy: Sync {
+    // Scratch space, each ranking thread gets its own
+    type Scratch;
+    fn scratch(&self) -> Self::Scratch;
+
+    fn rank<I: SaIndex>(&self, candidate: &Candidate, dict_index: &DictIndex<I>, scratch: &mut Self::Scratch) -> f64;
+
+    // Called once before any ranking, for strategies that keep their own statistics of the text
+    fn init<I: SaIndex>(&mut self, _dict_index: &DictIndex<I>) {}
+
+    // Called once the word is picked and split (at the sorted locations), before the next round of ranking
+    fn update<I: SaIndex>(&mut self, _word: &Word, _locations: &[usize], _dict_index: &DictIndex<I>) {}
+}
+
+pub fn rank<I: SaIndex, R: RankingStrategy>(m: &mut Match<I>, dict_index: &DictIndex<I>, strategy: &R, scratch: &mut R::Scratch) -> Option<Word> {
+    // From match_finder we know len >= 2 and sa_count >= 2 (if m is valid)
+    let (count, loc) = count(m, dict_index);
+    if count < dict_index.build.min_count {
+        m.is_valid = false;
+        return None;
+    }
+
+    let len = m.len;
+    let bytes = &dict_index.buf[loc..(loc + len as usize)];
+    let overhead = dict_index.build.byte_overhead * match &dict_index.grammar {
+        Some(grammar) => grammar.cost(bytes) as f64,
+        None => f64::from(len) + 1f64
+    };
+    let candidate = Candidate { bytes, location: loc, count, n: dict_index.n, overhead };
+    let rank = strategy.rank(&candidate, dict_index, scratch);
+
+    if rank <= dict_index.build.min_rank || rank.is_nan() {
+        m.is_valid = false;
+        return None;
+    }
+
+    Some(Word {
+        location: loc, len,
+        sa_index: m.sa_index.as_usize(), sa_count: m.sa_count.as_usize(),
+        count, documents: 0, rank
+    })
+}
+
+// Bytes saved by the classic estimate of (count - 1) * (len - 1), for byte oriented coders
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ByteSavings;
+
+impl RankingStrategy for ByteSavings {
+    type Scratch = ();
+    fn scratch(&self) {}
+
+    fn rank<I: SaIndex>(&self, candidate: &Candidate, _: &DictIndex<I>, _: &mut ()) -> f64 {
+        (candidate.count - 1) as f64 * (candidate.bytes.len() - 1) as f64
+    }
+}
+
+// Bytes saved for byte oriented coders less the bytes the word takes in the dictionary: every occurrence saves
+// len - 1 bytes, the overhead is subtracted as FixedWidth does
+#[derive(Clone, Copy, Default, Debug)]
+pub struct NetSavings;
+
+impl RankingStrategy for NetSavings {
+    type Scratch = ();
+    fn scratch(&self) {}
+
+    fn rank<I: SaIndex>(&self, candidate: &Candidate, _: &DictIndex<I>, _: &mut ()) -> f64 {
+        candidate.count as f64 * (candidate.bytes.len() - 1) as f64 - candidate.overhead / 8f64
+    }
+}
+
+// Bits saved when every symbol takes the same number of bits (16 for TokenEncoding::U16Be)
+#[derive(Clone, Copy, Debug)]
+pub struct FixedWidth {
+    pub bits: u32
+}
+
+impl Default for FixedWidth {
+    fn default() -> Self { Self { bits: 16 } }
+}
+
+impl RankingStrategy for FixedWidth {
+    type Scratch = ();
+    fn scratch(&self) {}
+
+    fn rank<I: SaIndex>(&self, candidate: &Candidate, _: &DictIndex<I>, _: &mut ()) -> f64 {
+        let len = candidate.bytes.len() as f64;
+        candidate.count as f64 * (len - 1f64) * f64::from(self.bits) - candidate.overhead
+    }
+}
+
+// Ranks with a user closure
+#[derive(Clone, Copy, Debug)]
+pub struct CustomRanking<F>(pub F);
+
+impl<F: Fn(&Candidate) -> f64 + Sync> RankingStrategy for CustomRanking<F> {
+    type Scratch = ();
+    fn scratch(&self) {}
+
+    fn rank<I: SaIndex>(&self, candidate: &Candidate, _: &DictIndex<I>, _: &mut ()) -> f64 {
+        (self.0)(candidate)
+    }
+}
@@ -0,0 +1,133 @@
+use crate::documents;
+use crate::incan74re::DictIndex;
+use crate::index::SaIndex;
+
+// Delimiters of records (lines of logs, fields of CSV) that words never span
+// A word holds no delimiter, unless whole allows it to be exactly one record with the delimiter that closes it
+// The bytes of a word are the same at every occurrence, so the match finder drops the lengths holding a delimiter
+// (see fit), only the start of a whole record differs between occurrences and is left to counting and split
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Records {
+    delimiters: [bool; 256],
+    whole:      Whole
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum Whole {
+    #[default]
+    None,
+    Fields,   // A field and the delimiter that closes it, starting after a delimiter
+    Lines(u8) // A line up to and including this byte (a delimiter as well), starting after one, it may hold other delimiters
+}
+
+impl Default for Records {
+    fn default() -> Self { Self { delimiters: [false; 256], whole: Whole::None } }
+}
+
+impl Records {
+    pub fn new(delimiters: &[u8], whole: Whole) -> Self {
+        let mut records = Self { delimiters: [false; 256], whole };
+        for &byte in delimiters { records.delimiters[byte as usize] = true; }
+        if let Whole::Lines(line) = whole { records.delimiters[line as usize] = true; }
+
+        records
+    }
+
+    pub fn is_active(&self) -> bool { self.delimiters.contains(&true) }
+    pub fn is_delimiter(&self, byte: u8) -> bool { self.delimiters[byte as usize] }
+}
+
+// The lengths of a match that fit the delimiters
+pub struct Fit {
+    pub plain: usize,        // Up to the first delimiter
+    pub whole: Option<usize> // The whole record ending with a delimiter, if allowed
+}
+
+// The fit of the match at loc of up to max_len bytes
+pub fn fit<I: SaIndex>(dict_index: &DictIndex<I>, loc: usize, max_len: usize) -> Fit {
+    let records = &dict_index.records;
+    if !records.is_active() { return Fit { plain: max_len, whole: None }; }
+
+    let bytes = &dict_index.buf[loc..(loc + max_len)];
+    let first = bytes.iter().position(|&byte| records.is_delimiter(byte));
+    let whole = match records.whole {
+        Whole::None => None,
+        Whole::Fields => first.map(|pos| pos + 1),
+        Whole::Lines(line) => bytes.iter().position(|&byte| byte == line).map(|pos| pos + 1)
+    };
+
+    Fit { plain: first.unwrap_or(max_len), whole }
+}
+
+// Whether the occurrence of a word of len bytes at loc fits the records
+// Only a whole record ends with a delimiter, it has to start after one (or at the start of a document)
+pub fn allows<I: SaIndex>(dict_index: &DictIndex<I>, loc: usize, len: usize) -> bool {
+    let (records, buf) = (&dict_index.records, &dict_index.buf);
+    if records.whole == Whole::None || !records.is_delimiter(buf[loc + len - 1]) { return true; }
+    if loc == 0 || documents::is_start(&dict_index.doc_starts, loc) { return true; }
+
+    match records.whole {
+        Whole::Lines(line) => buf[loc - 1] == line,
+        _ => records.is_delimiter(buf[loc - 1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Options;
+
+    // A few lines repeated often enough that whole lines outrank their fields
+    fn csv() -> Vec<u8> {
+        let lines = ["alice,berlin,0\n", "bob,paris,1\n", "carol,rome,0\n", "dave,berlin,1\n"];
+        (0..300).map(|i| lines[(i * 7 + i / 5) % lines.len()]).collect::<String>().into_bytes()
+    }
+
+    // Every word of the parse with the position it starts at
+    fn occurrences(buf: &[u8], records: Records) -> Vec<(usize, Vec<u8>)> {
+        let encoded = crate::encode(buf, Options { records, ..Default::default() }).unwrap();
+        assert!(!encoded.dictionary.is_empty());
+
+        let mut pos = 0;
+        let mut words = Vec::new();
+        for &token in &encoded.tokens {
+            if token < 256 { pos += 1; continue; }
+            let word = encoded.dictionary.words()[token as usize - 256].clone();
+            pos += word.len();
+            words.push((pos - word.len(), word));
+        }
+
+        words
+    }
+
+    #[test]
+    fn words_never_span_a_delimiter() {
+        let buf = csv();
+        for (_, word) in occurrences(&buf, Records::new(b",\n", Whole::None)) {
+            assert!(!word.iter().any(|&byte| byte == b',' || byte == b'\n'), "{:?}", String::from_utf8_lossy(&word));
+        }
+    }
+
+    #[test]
+    fn whole_records_are_complete() {
+        let buf = csv();
+        for (delimiters, whole, after) in [(&b",\n"[..], Whole::Fields, &b",\n"[..]), (b",", Whole::Lines(b'\n'), b"\n")] {
+            let records = Records::new(delimiters, whole);
+            let mut complete = 0;
+            for (loc, word) in occurrences(&buf, records) {
+                let delimiters = word.iter().filter(|&&byte| records.is_delimiter(byte)).count();
+                if delimiters == 0 { continue; }
+                complete += 1;
+
+                // A whole record ends with its only delimiter (a line may hold fields) and starts after one
+                let name = String::from_utf8_lossy(&word);
+                let last = *word.last().unwrap();
+                assert!(after.contains(&last), "{whole:?}: {name:?}");
+                if whole == Whole::Fields { assert_eq!(delimiters, 1, "{name:?}"); }
+                else                      { assert_eq!(word.iter().filter(|&&byte| byte == b'\n').count(), 1, "{name:?}"); }
+                assert!(loc == 0 || after.contains(&buf[loc - 1]), "{whole:?}: {name:?} at {loc}");
+            }
+            assert!(complete > 0, "{whole:?}: no whole records");
+        }
+    }
+}
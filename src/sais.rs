@@ -0,0 +1,215 @@
+use crate::backend::Backend;
+use crate::error::Result;
+use crate::index::SaIndex;
+
+// Pure rust SA and LCP construction: SA-IS (Nong, Zhang & Chan) and Kasai et al.
+// Slower and hungrier than libsais (it works with usize internally), but needs no C sources
+pub struct PureRust;
+
+impl Backend for PureRust {
+    fn suffix_array<I: SaIndex>(buf: &[u8]) -> Result<Vec<I>> {
+        Ok(sa_is(buf, 255).into_iter().map(I::from_usize).collect())
+    }
+
+    fn lcp_array<I: SaIndex>(buf: &[u8], sa: &[I]) -> Result<Vec<I>> {
+        let sa: Vec<usize> = sa.iter().map(|x| x.as_usize()).collect();
+        Ok(kasai(buf, &sa).into_iter().map(I::from_usize).collect())
+    }
+
+    fn suffix_array_int<I: SaIndex>(text: &mut [I], k: usize) -> Result<Vec<I>> {
+        let text: Vec<usize> = text.iter().map(|x| x.as_usize()).collect();
+        Ok(sa_is(&text, k.saturating_sub(1)).into_iter().map(I::from_usize).collect())
+    }
+}
+
+const EMPTY: usize = usize::MAX;
+
+// Suffix array of text over the alphabet [0 ..= upper]
+// A suffix that is a prefix of another suffix sorts first (as if the text ended with a unique smallest sentinel)
+pub fn sa_is<T: Copy + Into<usize>>(text: &[T], upper: usize) -> Vec<usize> {
+    let n = text.len();
+    match n {
+        0 => return Vec::new(),
+        1 => return vec![0],
+        2 => return if text[0].into() < text[1].into() { vec![0, 1] } else { vec![1, 0] },
+        _ => {}
+    }
+
+    let s = |i: usize| -> usize { text[i].into() };
+
+    // ls[i] is true for S-type suffixes (smaller than the next suffix)
+    let mut ls = vec![false; n];
+    for i in (0..(n - 1)).rev() {
+        ls[i] = if s(i) == s(i + 1) { ls[i + 1] } else { s(i) < s(i + 1) };
+    }
+
+    // Bucket starts for the L-type and S-type suffixes of each symbol
+    let mut sum_l = vec![0; upper + 1];
+    let mut sum_s = vec![0; upper + 1];
+    for i in 0..n {
+        if !ls[i] { sum_s[s(i)] += 1; }
+        else      { sum_l[s(i) + 1] += 1; }
+    }
+    for i in 0..=upper {
+        sum_s[i] += sum_l[i];
+        if i < upper { sum_l[i + 1] += sum_s[i]; }
+    }
+
+    let mut sa = vec![EMPTY; n];
+    let induce = |sa: &mut Vec<usize>, lms: &[usize]| {
+        sa.fill(EMPTY);
+        let mut buf = sum_s.clone();
+        for &d in lms {
+            if d == n { continue; }
+            sa[buf[s(d)]] = d;
+            buf[s(d)] += 1;
+        }
+
+        buf.copy_from_slice(&sum_l);
+        sa[buf[s(n - 1)]] = n - 1;
+        buf[s(n - 1)] += 1;
+        for i in 0..n {
+            let v = sa[i];
+            if v != EMPTY && v >= 1 && !ls[v - 1] {
+                sa[buf[s(v - 1)]] = v - 1;
+                buf[s(v - 1)] += 1;
+            }
+        }
+
+        buf.copy_from_slice(&sum_l);
+        for i in (0..n).rev() {
+            let v = sa[i];
+            if v != EMPTY && v >= 1 && ls[v - 1] {
+                buf[s(v - 1) + 1] -= 1;
+                sa[buf[s(v - 1) + 1]] = v - 1;
+            }
+        }
+    };
+
+    // Left-most S-type positions
+    let mut lms_map = vec![EMPTY; n + 1];
+    let mut lms = Vec::new();
+    for i in 1..n {
+        if !ls[i - 1] && ls[i] {
+            lms_map[i] = lms.len();
+            lms.push(i);
+        }
+    }
+
+    induce(&mut sa, &lms);
+
+    if !lms.is_empty() {
+        let m = lms.len();
+        let mut sorted_lms: Vec<usize> = sa.iter().copied().filter(|&v| lms_map[v] != EMPTY).collect();
+
+        // Name the LMS substrings and sort them recursively
+        let mut rec_text = vec![0; m];
+        let mut rec_upper = 0;
+        rec_text[lms_map[sorted_lms[0]]] = 0;
+        for i in 1..m {
+            let (mut l, mut r) = (sorted_lms[i - 1], sorted_lms[i]);
+            let end_l = if lms_map[l] + 1 < m { lms[lms_map[l] + 1] } else { n };
+            let end_r = if lms_map[r] + 1 < m { lms[lms_map[r] + 1] } else { n };
+
+            let mut same = true;
+            if end_l - l != end_r - r {
+                same = false;
+            } else {
+                while l < end_l && s(l) == s(r) {
+                    l += 1;
+                    r += 1;
+                }
+                if l == n || s(l) != s(r) { same = false; }
+            }
+
+            if !same { rec_upper += 1; }
+            rec_text[lms_map[sorted_lms[i]]] = rec_upper;
+        }
+
+        let rec_sa = sa_is(&rec_text, rec_upper);
+        for (sorted, &rec) in sorted_lms.iter_mut().zip(rec_sa.iter()) {
+            *sorted = lms[rec];
+        }
+        induce(&mut sa, &sorted_lms);
+    }
+
+    sa
+}
+
+// LCP array in the layout of Backend::lcp_array: lcp[i] = lcp(SA[i], SA[i+1]), ending with 0
+pub fn kasai<T: Eq>(text: &[T], sa: &[usize]) -> Vec<usize> {
+    let n = text.len();
+    let mut rank = vec![0; n];
+    for (i, &suffix) in sa.iter().enumerate() {
+        rank[suffix] = i;
+    }
+
+    let mut lcp = vec![0; n];
+    let mut h = 0;
+    for i in 0..n {
+        if rank[i] + 1 >= n { h = 0; continue; }
+
+        let j = sa[rank[i] + 1];
+        while i + h < n && j + h < n && text[i + h] == text[j + h] { h += 1; }
+        lcp[rank[i]] = h;
+        h = h.saturating_sub(1);
+    }
+
+    lcp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sorts the suffixes directly, a suffix that is a prefix of another sorts first as in sa_is
+    fn naive_sa(text: &[u8]) -> Vec<usize> {
+        let mut sa: Vec<usize> = (0..text.len()).collect();
+        sa.sort_by(|&a, &b| text[a..].cmp(&text[b..]));
+        sa
+    }
+
+    fn naive_lcp(text: &[u8], sa: &[usize]) -> Vec<usize> {
+        let common = |a: usize, b: usize| text[a..].iter().zip(&text[b..]).take_while(|(x, y)| x == y).count();
+        (0..sa.len()).map(|i| if i + 1 < sa.len() { common(sa[i], sa[i + 1]) } else { 0 }).collect()
+    }
+
+    fn texts() -> Vec<Vec<u8>> {
+        let mut seed = 3u32;
+        let mut random = |alphabet: u32| -> Vec<u8> {
+            (0..2000).map(|_| { seed = seed.wrapping_mul(1103515245).wrapping_add(12345); ((seed >> 16) % alphabet) as u8 }).collect()
+        };
+
+        vec![Vec::new(), vec![7], vec![b'a'; 1000], b"abracadabra".to_vec(), b"mississippi$".to_vec(), random(2), random(4), random(256)]
+    }
+
+    #[test]
+    fn matches_the_sorted_suffixes() {
+        for text in texts() {
+            let sa = sa_is(&text, 255);
+            assert_eq!(sa, naive_sa(&text), "{text:?}");
+            assert_eq!(kasai(&text, &sa), naive_lcp(&text, &sa), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn integer_alphabets() {
+        // Symbols past u8, as for several documents (see documents.rs)
+        let text: Vec<usize> = (0..500).map(|i| (i * i) % 300).collect();
+        let mut naive: Vec<usize> = (0..text.len()).collect();
+        naive.sort_by(|&a, &b| text[a..].cmp(&text[b..]));
+        assert_eq!(sa_is(&text, 299), naive);
+    }
+
+    #[cfg(feature = "libsais")]
+    #[test]
+    fn matches_libsais() {
+        use crate::backend::Libsais;
+
+        for text in texts() {
+            let sa = PureRust::suffix_array::<i32>(&text).unwrap();
+            assert_eq!(sa, Libsais::suffix_array::<i32>(&text).unwrap(), "{text:?}");
+            assert_eq!(PureRust::lcp_array(&text, &sa).unwrap(), Libsais::lcp_array(&text, &sa).unwrap(), "{text:?}");
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::thread;
+
+use crate::incan74re::{cmp_f64, DictIndex, Word};
+use crate::index::SaIndex;
+use crate::match_finder::Match;
+use crate::ranking::{rank, RankingStrategy};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Selection {
+    // Keeps the matches in a max-heap of cached ranks and only re-ranks stale ones when they reach the top
+    // Exact as long as ranks never grow after a split (as in lazy greedy submodular maximisation), close otherwise
+    #[default]
+    Lazy,
+    // Re-ranks every match on every iteration
+    Exhaustive
+}
+
+// Runs f over contiguous chunks of the matches, one per thread, and returns the results in chunk order
+// f gets the index of the chunk's first match
+fn map_chunks<I, R, F>(matches: &mut [Match<I>], threads: usize, f: F) -> Vec<R>
+where I: SaIndex, R: Send, F: Fn(&mut [Match<I>], usize) -> R + Sync {
+    // Not worth spawning threads for a few matches
+    let chunk_size = matches.len().div_ceil(threads).max(1 << 14);
+    if threads == 1 || chunk_size >= matches.len() { return vec![f(matches, 0)]; }
+
+    thread::scope(|s| {
+        let f = &f;
+        let handles: Vec<_> = matches.chunks_mut(chunk_size)
+            .enumerate()
+            .map(|(i, chunk)| s.spawn(move || f(chunk, i * chunk_size)))
+            .collect();
+
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+// Reduces the best word of each chunk in order
+// max_by keeps the last of equal elements, so the result is the same as a single pass over all matches
+pub fn rank_matches<I: SaIndex, R: RankingStrategy>(matches: &mut [Match<I>], dict_index: &DictIndex<I>, strategy: &R, threads: usize) -> Option<Word> {
+    let best_words = map_chunks(matches, threads, |chunk, _| {
+        let mut scratch = strategy.scratch();
+        chunk.iter_mut()
+            .filter(|m| m.is_valid)
+            .filter_map(|m| rank(m, dict_index, strategy, &mut scratch))
+            .max_by(|x, y| cmp_f64(x.rank, y.rank))
+    });
+
+    best_words.into_iter()
+        .flatten()
+        .max_by(|x, y| cmp_f64(x.rank, y.rank))
+}
+
+struct Entry {
+    rank: f64,
+    index: usize,   // Into the matches
+    iteration: u32  // When the rank was computed
+}
+
+// Ranks are above the min_rank of BuildOptions and never NaN (see ranking::rank), they may be subnormal, or 0 and
+// negative with a min_rank below 0, total_cmp orders any of them, ties are broken by the match index to stay deterministic
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank.total_cmp(&other.rank).then(self.index.cmp(&other.index))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for Entry {}
+
+pub struct RankQueue<S> {
+    heap: BinaryHeap<Entry>,
+    iteration: u32,
+    scratch: S
+}
+
+impl<S> RankQueue<S> {
+    // Ranks all matches once, matches that can't become words are invalidated and left out
+    pub fn new<I, R>(matches: &mut [Match<I>], dict_index: &DictIndex<I>, strategy: &R, threads: usize) -> Self
+    where I: SaIndex, R: RankingStrategy<Scratch = S> {
+        let entries = map_chunks(matches, threads, |chunk, offset| {
+            let mut scratch = strategy.scratch();
+            chunk.iter_mut()
+                .enumerate()
+                .filter(|(_, m)| m.is_valid)
+                .filter_map(|(i, m)| rank(m, dict_index, strategy, &mut scratch).map(|w| Entry { rank: w.rank, index: offset + i, iteration: 0 }))
+                .collect::<Vec<_>>()
+        });
+
+        Self { heap: entries.into_iter().flatten().collect(), iteration: 0, scratch: strategy.scratch() }
+    }
+
+    // Pops stale entries and re-ranks them until the top of the heap is up to date
+    pub fn pop_best<I, R>(&mut self, matches: &mut [Match<I>], dict_index: &DictIndex<I>, strategy: &R) -> Option<Word>
+    where I: SaIndex, R: RankingStrategy<Scratch = S> {
+        while let Some(entry) = self.heap.pop() {
+            let m = &mut matches[entry.index];
+            if !m.is_valid { continue; }
+
+            let Some(word) = rank(m, dict_index, strategy, &mut self.scratch) else { continue };
+            if entry.iteration == self.iteration { return Some(word); }
+
+            self.heap.push(Entry { rank: word.rank, index: entry.index, iteration: self.iteration });
+        }
+
+        None
+    }
+
+    // Marks all cached ranks as stale, call after every split
+    pub fn advance(&mut self) { self.iteration += 1; }
+}
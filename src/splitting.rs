@@ -1,25 +1,30 @@
+use crate::counting::fits;
 use crate::incan74re::{DictIndex, Word};
+use crate::index::SaIndex;
 
 // TODO: Align the offsets array to the suffix array using the inverseSA and lower L3 cache misses on ranking
 // This is a crucial loop, even tho it gets executed only once per iteration, it's O(n)
 // Note that we get a small speedup in doing parsing and offsets reseting together because
 // 1) It's only O(n), we're not doing 2 passes
 // 2) We're doing them in different directions -> gives us an exra speedup
-pub fn split(word: &Word, dict_index: &mut DictIndex) {
+// Returns the sorted locations that were replaced by the word
+pub fn split<I: SaIndex>(word: &Word, dict_index: &mut DictIndex<I>) -> Vec<usize> {
     // Find word from SA
-    let mut locations = vec![0; word.sa_count as usize];
+    let mut locations = vec![I::ZERO; word.sa_count];
     locations.copy_from_slice(&dict_index.sa[word.get_sa_range()]);
     locations.sort_unstable();
 
-    let effective_len = i32::from(word.len) - 1;
-    let word_len      = usize::from(word.len);
+    let effective_len = I::from_usize(word.len as usize - 1);
+    let word_len      = word.len as usize;
     let rt = dict_index.replacement_token; // used for parsing later
-    dict_index.replacement_token -= 1;
+    dict_index.replacement_token = rt - I::from(1);
+    let mut replaced = Vec::with_capacity(word.count as usize);
 
     // Parse this word
     for loc in locations {
-        let loc = loc as usize;
-        if dict_index.offsets[loc] < effective_len { continue; }
+        let loc = loc.as_usize();
+        if dict_index.offsets[loc] < effective_len || !fits(dict_index, loc, word_len) { continue; }
+        replaced.push(loc);
 
         // Replace locations of the word with a token for parsing
         dict_index.offsets[loc..(loc + word_len)]
@@ -30,8 +35,10 @@ pub fn split(word: &Word, dict_index: &mut DictIndex) {
         // TODO: Unroll?
         // Calculate offsets, traversing the vec backwards
         for (last, offset) in dict_index.offsets[..loc].iter_mut().rev().enumerate() {
-            if *offset < 0 { break; }
-            *offset = last.try_into().unwrap();
+            if *offset < I::ZERO { break; }
+            *offset = I::from_usize(last);
         }
     }
+
+    replaced
 }
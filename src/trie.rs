@@ -0,0 +1,66 @@
+// A byte trie of words, walked from a position to find every word that starts there
+
+#[derive(Clone, Default, Debug)]
+pub struct Trie {
+    nodes: Vec<Node> // The root is node 0
+}
+
+#[derive(Clone, Default, Debug)]
+struct Node {
+    children: Vec<(u8, u32)>, // Sorted by the byte
+    word: Option<u32>
+}
+
+impl Trie {
+    pub fn new() -> Self { Self { nodes: vec![Node::default()] } }
+
+    fn child(&self, node: usize, byte: u8) -> Option<usize> {
+        let children = &self.nodes[node].children;
+        children.binary_search_by_key(&byte, |&(b, _)| b).ok().map(|i| children[i].1 as usize)
+    }
+
+    // Later duplicates keep the index of the first word, the empty word is never matched
+    pub fn insert(&mut self, word: &[u8], index: usize) {
+        let mut node = 0;
+        for &byte in word {
+            node = match self.nodes[node].children.binary_search_by_key(&byte, |&(b, _)| b) {
+                Ok(i) => self.nodes[node].children[i].1 as usize,
+                Err(i) => {
+                    let next = self.nodes.len();
+                    self.nodes[node].children.insert(i, (byte, next as u32));
+                    self.nodes.push(Node::default());
+                    next
+                }
+            };
+        }
+        self.nodes[node].word.get_or_insert(index as u32);
+    }
+
+    // The words that bytes starts with, as (length, index) from the shortest
+    pub fn prefixes<'a>(&'a self, bytes: &'a [u8]) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let mut node = 0;
+        bytes.iter().map_while(move |&byte| {
+            node = self.child(node, byte)?;
+            Some(self.nodes[node].word)
+        })
+        .enumerate()
+        .filter_map(|(i, word)| word.map(|word| (i + 1, word as usize)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_from_the_shortest() {
+        let mut trie = Trie::new();
+        for (index, word) in [&b"the"[..], b"th", b"then", b"", b"the", b"a"].iter().enumerate() { trie.insert(word, index); }
+
+        let prefixes: Vec<(usize, usize)> = trie.prefixes(b"thenceforth").collect();
+        assert_eq!(prefixes, [(2, 1), (3, 0), (4, 2)]);
+        assert_eq!(trie.prefixes(b"at").collect::<Vec<_>>(), [(1, 5)]);
+        assert_eq!(trie.prefixes(b"x").count(), 0);
+        assert_eq!(trie.prefixes(b"").count(), 0);
+    }
+}
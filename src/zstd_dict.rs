@@ -0,0 +1,528 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::Write;
+
+use crate::checksum::crc32;
+use crate::error::{Error, Result};
+
+// Exports the words as a zstd dictionary (RFC 8878, section 5), all integers are little-endian
+//
+// offset  size  field
+// 0       4     magic 0xEC30A437
+// 4       4     dictionary ID
+// 8       x     entropy tables: the literals Huffman table, then the FSE tables of offsets, match and literal lengths
+// 8+x     12    repeat offsets 1, 4 and 8, as u32
+// 20+x    y     content, the words from the lowest to the highest gain
+//
+// zstd uses the tables for the first block of every frame, so they are computed from the training data as parsed by
+// the words: a word token is a match of the word in the content (zstd's minimum match is 3 bytes, shorter words stay
+// literals), the literals between two matches are their literal length, and the offset is the distance from the
+// end of the content to the word, as if the data started right after the content
+// Like zstd's dictionary builder, every symbol keeps a count of at least 1, so all the tables stay usable
+
+const MAGIC: u32 = 0xEC30_A437;
+const MIN_MATCH: usize = 3;
+const REP_OFFSETS: [u32; 3] = [1, 4, 8];
+const MIN_CONTENT: usize = 8; // The repeat offsets must point into the content
+
+const MAX_HUFFMAN_BITS: u32 = 11;
+const MIN_HUFFMAN_BITS: u32 = 9;
+const WEIGHTS_LOG: u32 = 6;
+const OFFSETS_LOG: u32 = 8;
+const MATCH_LENGTHS_LOG: u32 = 9;
+const LITERAL_LENGTHS_LOG: u32 = 9;
+const MAX_OFFSET_CODE: usize = 31;
+
+// The smallest value of each length code, the rest is sent as extra bits
+const LITERAL_LENGTH_BASES: [u32; 36] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+    16, 18, 20, 22, 24, 28, 32, 40, 48, 64, 128, 256, 512, 1024, 2048, 4096,
+    8192, 16384, 32768, 65536
+];
+const MATCH_LENGTH_BASES: [u32; 53] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18,
+    19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34,
+    35, 37, 39, 41, 43, 47, 51, 59, 67, 83, 99, 131, 259, 515, 1027, 2051,
+    4099, 8195, 16387, 32771, 65539
+];
+
+fn highbit(value: u64) -> u32 { 63 - value.leading_zeros() }
+
+fn length_code(value: usize, bases: &[u32]) -> usize {
+    bases.partition_point(|&base| base as usize <= value) - 1
+}
+
+// Offsets are sent as offset + 3, the values 1-3 are the repeat offsets
+fn offset_code(offset: usize) -> usize {
+    (highbit(offset as u64 + 3) as usize).min(MAX_OFFSET_CODE)
+}
+
+// Words holds the bytes of every word token, order the indexes of all the words from the lowest to the highest gain
+// dict_id 0 derives the ID from the content, like zstd's dictionary builder
+pub fn write<W: Write>(words: &[&[u8]], order: &[usize], tokens: &[u32], dict_id: u32, writer: &mut W) -> Result<()> {
+    let mut content = Vec::new();
+    let mut starts = vec![0; words.len()];
+    for &index in order {
+        starts[index] = content.len();
+        content.extend_from_slice(words[index]);
+    }
+    if content.len() < MIN_CONTENT { return Err(Error::DictionaryTooSmall { len: content.len(), min: MIN_CONTENT }); }
+
+    // IDs below 32768 are reserved for registered dictionaries
+    let dict_id = if dict_id != 0 { dict_id } else { crc32(&content) % ((1 << 31) - 32768) + 32768 };
+
+    // Offsets up to the content and a 128 KiB block must be codable
+    let max_offset_code = offset_code(content.len() + (128 << 10));
+    let mut literals = [1u64; 256];
+    let mut offsets = vec![1u64; max_offset_code + 1];
+    let mut match_lengths = [1u64; MATCH_LENGTH_BASES.len()];
+    let mut literal_lengths = [1u64; LITERAL_LENGTH_BASES.len()];
+
+    let mut run = 0;
+    for &token in tokens {
+        let token = token as usize;
+        let word = if token < 256 { None } else { Some(words[token - 256]) };
+        match word {
+            Some(word) if word.len() >= MIN_MATCH => {
+                literal_lengths[length_code(run, &LITERAL_LENGTH_BASES)] += 1;
+                match_lengths[length_code(word.len(), &MATCH_LENGTH_BASES)] += 1;
+                offsets[offset_code(content.len() - starts[token - 256])] += 1;
+                run = 0;
+            },
+            Some(word) => {
+                word.iter().for_each(|&byte| literals[usize::from(byte)] += 1);
+                run += word.len();
+            },
+            None => {
+                literals[token] += 1;
+                run += 1;
+            }
+        }
+    }
+
+    let mut out = Vec::with_capacity(content.len() + 1024);
+    out.extend_from_slice(&MAGIC.to_le_bytes());
+    out.extend_from_slice(&dict_id.to_le_bytes());
+    out.extend(huffman_table(&literals));
+    for (counts, log) in [(&offsets[..], OFFSETS_LOG), (&match_lengths, MATCH_LENGTHS_LOG), (&literal_lengths, LITERAL_LENGTHS_LOG)] {
+        let mut bits = BitWriter::default();
+        write_ncount(&normalize(counts, log), log, &mut bits);
+        out.extend(bits.bytes());
+    }
+    for rep in REP_OFFSETS { out.extend_from_slice(&rep.to_le_bytes()); }
+    out.extend_from_slice(&content);
+
+    Ok(writer.write_all(&out)?)
+}
+
+// Bits are written from the lsb of each byte
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    bits: u32
+}
+
+impl BitWriter {
+    fn add(&mut self, value: u64, bits: u32) {
+        self.acc |= value << self.bits;
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.out.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn bytes(mut self) -> Vec<u8> {
+        if self.bits > 0 { self.out.push(self.acc as u8); }
+        self.out
+    }
+}
+
+// Scales the counts to sum to 1 << log, every symbol that occurs keeps at least 1
+fn normalize(counts: &[u64], log: u32) -> Vec<u32> {
+    let size = 1u32 << log;
+    let total = counts.iter().sum::<u64>() as f64;
+    let mut norm: Vec<u32> = counts.iter()
+        .map(|&count| if count == 0 { 0 } else { ((count as f64 * f64::from(size) / total).round() as u32).max(1) })
+        .collect();
+
+    // Take the rounding errors from the largest
+    let mut sum: u32 = norm.iter().sum();
+    while sum > size {
+        let (index, _) = norm.iter().enumerate().max_by_key(|&(_, &n)| n).unwrap();
+        norm[index] -= 1;
+        sum -= 1;
+    }
+    while sum < size {
+        let (index, _) = counts.iter().enumerate().max_by_key(|&(_, &count)| count).unwrap();
+        norm[index] += 1;
+        sum += 1;
+    }
+
+    norm
+}
+
+// The FSE table description, follows FSE_writeNCount of the reference implementation
+fn write_ncount(norm: &[u32], log: u32, bits: &mut BitWriter) {
+    bits.add(u64::from(log - 5), 4);
+
+    // One more than the table, so a count of 0 can be told apart
+    let mut remaining = (1i64 << log) + 1;
+    let mut threshold = 1i64 << log;
+    let mut width = log + 1;
+    let mut symbol = 0;
+    let mut previous_zero = false;
+
+    while symbol < norm.len() && remaining > 1 {
+        // Runs of zero counts are sent as repeat flags, 3 means 3 more zeros and another flag follows
+        if previous_zero {
+            let mut start = symbol;
+            while norm[symbol] == 0 { symbol += 1; }
+            while symbol >= start + 24 {
+                start += 24;
+                bits.add(0xffff, 16);
+            }
+            while symbol >= start + 3 {
+                start += 3;
+                bits.add(3, 2);
+            }
+            bits.add((symbol - start) as u64, 2);
+        }
+
+        let count = i64::from(norm[symbol]);
+        symbol += 1;
+        let max = 2 * threshold - 1 - remaining;
+        remaining -= count;
+
+        // Values below max take a bit less
+        let mut value = count + 1;
+        if value >= threshold { value += max; }
+        bits.add(value as u64, if value < max { width - 1 } else { width });
+        previous_zero = count == 0;
+
+        while remaining < threshold {
+            width -= 1;
+            threshold >>= 1;
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct State {
+    symbol:   usize,
+    bits:     u32,
+    baseline: u32
+}
+
+// The decoding table of RFC 8878, section 4.1.1
+fn decode_table(norm: &[u32], log: u32) -> Vec<State> {
+    let size = 1usize << log;
+    let step = (size >> 1) + (size >> 3) + 3;
+    let mut symbols = vec![0; size];
+    let mut position = 0;
+    for (symbol, &count) in norm.iter().enumerate() {
+        for _ in 0..count {
+            symbols[position] = symbol;
+            position = (position + step) & (size - 1);
+        }
+    }
+
+    let mut next = norm.to_vec();
+    symbols.into_iter().map(|symbol| {
+        let state = next[symbol];
+        next[symbol] += 1;
+        let bits = log - highbit(u64::from(state));
+        State { symbol, bits, baseline: (state << bits) - size as u32 }
+    }).collect()
+}
+
+// FSE codes the symbols with two interleaved states, into a stream that's read backwards
+// The decoder takes the symbols of even indexes from the first state and stops when it runs out of bits
+fn fse_encode(symbols: &[usize], norm: &[u32], log: u32) -> Vec<u8> {
+    let table = decode_table(norm, log);
+
+    // The states of each symbol split the next states between them
+    let mut previous = vec![vec![0; table.len()]; norm.len()];
+    for (state, entry) in table.iter().enumerate() {
+        for next in entry.baseline..(entry.baseline + (1 << entry.bits)) { previous[entry.symbol][next as usize] = state; }
+    }
+
+    // The last two symbols are the first states, reading bits after the second last one has to run out of bits
+    let first = |symbol: usize| (0..table.len()).filter(|&state| table[state].symbol == symbol).max_by_key(|&state| table[state].bits).unwrap();
+
+    let n = symbols.len();
+    let mut bits = BitWriter::default();
+    let mut states = [0; 2];
+    for (i, &symbol) in symbols.iter().enumerate().rev() {
+        if i + 2 >= n { states[i & 1] = first(symbol); continue; }
+
+        let next = states[i & 1];
+        let state = previous[symbol][next];
+        bits.add((next - table[state].baseline as usize) as u64, table[state].bits);
+        states[i & 1] = state;
+    }
+
+    bits.add(states[1] as u64, log);
+    bits.add(states[0] as u64, log);
+    // The end mark, the decoder starts after the highest set bit
+    bits.add(1, 1);
+    bits.bytes()
+}
+
+// Code lengths of a complete Huffman code of at most max_bits, all counts must be at least 1
+fn huffman_lengths(counts: &[u64], max_bits: u32) -> Vec<u32> {
+    let n = counts.len();
+    let mut heap: BinaryHeap<_> = counts.iter().enumerate().map(|(symbol, &count)| Reverse((count, symbol))).collect();
+    let mut parents = vec![0; n];
+    while heap.len() > 1 {
+        let Reverse((a, i)) = heap.pop().unwrap();
+        let Reverse((b, j)) = heap.pop().unwrap();
+        let node = parents.len();
+        parents.push(0);
+        parents[i] = node;
+        parents[j] = node;
+        heap.push(Reverse((a + b, node)));
+    }
+
+    // Parents come after their children, the root is last
+    let mut depths = vec![0; parents.len()];
+    for node in (0..(parents.len() - 1)).rev() { depths[node] = depths[parents[node]] + 1; }
+    let mut lengths: Vec<u32> = depths[..n].iter().map(|&depth| depth.min(max_bits)).collect();
+
+    // Kraft sum in units of the longest code, lengthen the rarest symbols until it fits
+    // and then shorten the most frequent ones until the code is complete
+    let mut by_count: Vec<usize> = (0..n).collect();
+    by_count.sort_by_key(|&symbol| (counts[symbol], symbol));
+    let full = 1u64 << max_bits;
+    let mut kraft: u64 = lengths.iter().map(|&len| 1 << (max_bits - len)).sum();
+    while kraft > full {
+        let &symbol = by_count.iter().find(|&&symbol| lengths[symbol] < max_bits).unwrap();
+        lengths[symbol] += 1;
+        kraft -= 1 << (max_bits - lengths[symbol]);
+    }
+    while kraft < full {
+        let &symbol = by_count.iter().rev()
+            .find(|&&symbol| lengths[symbol] > 1 && kraft + (1 << (max_bits - lengths[symbol])) <= full)
+            .unwrap();
+        kraft += 1 << (max_bits - lengths[symbol]);
+        lengths[symbol] -= 1;
+    }
+
+    // FSE can't code a single weight, give equal lengths (all 8) a second and third one
+    if lengths.iter().all(|&len| len == lengths[0]) && n > 2 {
+        lengths[by_count[n - 1]] -= 1;
+        lengths[by_count[0]] += 1;
+        lengths[by_count[1]] += 1;
+    }
+
+    lengths
+}
+
+// The Huffman tree description: a header byte with the size of the FSE coded weights of the symbols 0-254,
+// the weight of 255 is implied by the others
+// Weight 0 is an unused symbol, otherwise it's max_bits + 1 - the code length
+fn huffman_table(counts: &[u64; 256]) -> Vec<u8> {
+    // The header can't describe more than 127 bytes, fewer bits leave fewer distinct weights,
+    // with 9 bits there are 3 which always fit
+    let mut table = Vec::new();
+    for max_bits in (MIN_HUFFMAN_BITS..=MAX_HUFFMAN_BITS).rev() {
+        let lengths = huffman_lengths(counts, max_bits);
+        let longest = *lengths.iter().max().unwrap();
+        let weights: Vec<usize> = lengths[..255].iter().map(|&len| (longest + 1 - len) as usize).collect();
+
+        let mut weight_counts = vec![0u64; longest as usize + 1];
+        for &weight in &weights { weight_counts[weight] += 1; }
+        let norm = normalize(&weight_counts, WEIGHTS_LOG);
+
+        let mut bits = BitWriter::default();
+        write_ncount(&norm, WEIGHTS_LOG, &mut bits);
+        table = bits.bytes();
+        table.extend(fse_encode(&weights, &norm, WEIGHTS_LOG));
+        if table.len() < 128 { break; }
+    }
+
+    table.insert(0, table.len() as u8);
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reads the bits of a backward stream, from the last written
+    struct BitReader<'a> {
+        data: &'a [u8],
+        pos: usize // Bits left
+    }
+
+    impl<'a> BitReader<'a> {
+        fn new(data: &'a [u8]) -> Self {
+            let last = *data.last().unwrap();
+            assert_ne!(last, 0, "the last byte holds the end mark");
+            Self { data, pos: data.len() * 8 - last.leading_zeros() as usize - 1 }
+        }
+
+        // None once more bits are read than there are
+        fn read(&mut self, bits: u32) -> Option<u32> {
+            let bits = bits as usize;
+            if bits > self.pos { return None; }
+            self.pos -= bits;
+            Some((0..bits).map(|i| u32::from((self.data[(self.pos + i) / 8] >> ((self.pos + i) % 8)) & 1) << i).sum())
+        }
+    }
+
+    // RFC 8878, section 4.1.1, returns the normalized counts (-1 is a probability below 1), the log and the size
+    fn read_ncount(data: &[u8]) -> (Vec<i32>, u32, usize) {
+        let mut pos = 0;
+        let mut read = |bits: u32, consume: bool| {
+            let value = (0..bits as usize).map(|i| u32::from((data[(pos + i) / 8] >> ((pos + i) % 8)) & 1) << i).sum::<u32>();
+            if consume { pos += bits as usize; }
+            value
+        };
+
+        let log = read(4, true) + 5;
+        let mut remaining = (1i32 << log) + 1;
+        let mut threshold = 1i32 << log;
+        let mut width = log + 1;
+        let mut norm = Vec::new();
+        while remaining > 1 {
+            if norm.last() == Some(&0) {
+                loop {
+                    let repeat = read(2, true);
+                    norm.extend(std::iter::repeat_n(0, repeat as usize));
+                    if repeat < 3 { break; }
+                }
+            }
+
+            let max = 2 * threshold - 1 - remaining;
+            let low = read(width - 1, false) as i32;
+            let value = if low < max {
+                read(width - 1, true);
+                low
+            } else {
+                let value = read(width, true) as i32;
+                if value >= threshold { value - max } else { value }
+            };
+
+            let count = value - 1;
+            remaining -= count.abs();
+            norm.push(count);
+            while remaining < threshold {
+                width -= 1;
+                threshold >>= 1;
+            }
+        }
+
+        assert_eq!(remaining, 1);
+        (norm, log, pos.div_ceil(8))
+    }
+
+    fn check_table(data: &[u8], max_log: u32, max_symbol: usize) -> usize {
+        let (norm, log, size) = read_ncount(data);
+        assert!((5..=max_log).contains(&log));
+        assert!(norm.len() <= max_symbol + 1);
+        assert_eq!(norm.iter().map(|&n| n.abs()).sum::<i32>(), 1 << log);
+        size
+    }
+
+    #[test]
+    fn structure_follows_the_format() {
+        let words: [&[u8]; 5] = [b"the quick brown fox ", b"jumps over ", b"the lazy dog", b"ab", b"\xff\xfe\x80"];
+        let mut tokens = Vec::new();
+        for round in 0..200u32 {
+            tokens.extend([256, 257 + round % 3, 259, 260]);
+            tokens.extend((0..(round % 40)).map(|i| (i * 7 + round) % 256));
+        }
+        let order = [3, 4, 2, 1, 0];
+
+        let mut out = Vec::new();
+        write(&words, &order, &tokens, 0, &mut out).unwrap();
+
+        assert_eq!(out[..4], MAGIC.to_le_bytes());
+        let dict_id = u32::from_le_bytes(out[4..8].try_into().unwrap());
+        assert!((32768..(1 << 31)).contains(&dict_id));
+
+        // Huffman weights, FSE compressed
+        let header = usize::from(out[8]);
+        assert!(header < 128);
+        let weights = &out[9..(9 + header)];
+        let (norm, log, size) = read_ncount(weights);
+        assert!(log <= WEIGHTS_LOG);
+        let norm: Vec<u32> = norm.iter().map(|&n| n as u32).collect();
+        let table = decode_table(&norm, log);
+
+        let mut reader = BitReader::new(&weights[size..]);
+        let mut states = [reader.read(log).unwrap() as usize, reader.read(log).unwrap() as usize];
+        let mut decoded = Vec::new();
+        'decode: loop {
+            for i in 0..2 {
+                let entry = table[states[i]];
+                decoded.push(entry.symbol);
+                match reader.read(entry.bits) {
+                    Some(bits) => states[i] = (entry.baseline + bits) as usize,
+                    None => {
+                        decoded.push(table[states[1 - i]].symbol);
+                        break 'decode;
+                    }
+                }
+            }
+        }
+        assert_eq!(decoded.len(), 255);
+
+        // The last weight completes the code
+        let total: u32 = decoded.iter().filter(|&&w| w > 0).map(|&w| 1 << (w - 1)).sum();
+        let max_bits = highbit(u64::from(total)) + 1;
+        assert!(max_bits <= MAX_HUFFMAN_BITS);
+        let rest = (1 << max_bits) - total;
+        assert!(rest.is_power_of_two());
+        decoded.push(highbit(u64::from(rest)) as usize + 1);
+        let longest = decoded.iter().filter(|&&w| w == 1).count();
+        assert!(longest >= 2 && longest % 2 == 0);
+
+        let mut pos = 9 + header;
+        pos += check_table(&out[pos..], OFFSETS_LOG, MAX_OFFSET_CODE);
+        pos += check_table(&out[pos..], MATCH_LENGTHS_LOG, MATCH_LENGTH_BASES.len() - 1);
+        pos += check_table(&out[pos..], LITERAL_LENGTHS_LOG, LITERAL_LENGTH_BASES.len() - 1);
+
+        let content: Vec<u8> = order.iter().flat_map(|&index| words[index].iter().copied()).collect();
+        for (i, rep) in REP_OFFSETS.iter().enumerate() {
+            let value = u32::from_le_bytes(out[(pos + 4 * i)..(pos + 4 * i + 4)].try_into().unwrap());
+            assert_eq!(value, *rep);
+            assert!(value as usize <= content.len());
+        }
+        assert_eq!(out[(pos + 12)..], content);
+        assert!(out.ends_with(words[0]));
+    }
+
+    // The dictionary of the words and tokens below as written when it was checked with zstd 1.5.7:
+    // zstd -D compresses and decompresses with it, and fails to load it with a byte of the tables flipped
+    const CHECKED: [u8; 176] = [
+        0x37, 0xa4, 0x30, 0xec, 0x44, 0x30, 0xb3, 0x00, 0x22, 0x11, 0x20, 0x73, 0x48, 0x07, 0xbf, 0xb6,
+        0xbf, 0xb6, 0xbf, 0xb6, 0xbf, 0xb6, 0xbf, 0xb6, 0xbf, 0xb6, 0xbf, 0xb6, 0xff, 0xf2, 0xff, 0xff,
+        0x7f, 0xe5, 0xa6, 0x7e, 0x55, 0xe5, 0xf9, 0xff, 0xff, 0xf7, 0xee, 0x33, 0x30, 0x30, 0x30, 0x78,
+        0x3a, 0x9f, 0x99, 0x99, 0x99, 0x6d, 0x1b, 0x44, 0x80, 0x00, 0x01, 0x02, 0x02, 0x02, 0x02, 0x02,
+        0x3d, 0x7a, 0x04, 0x02, 0x81, 0x40, 0x20, 0x10, 0xf2, 0x08, 0x82, 0x20, 0x08, 0x82, 0x20, 0x08,
+        0x82, 0x20, 0x08, 0x82, 0x20, 0x08, 0x21, 0x84, 0x10, 0x42, 0x08, 0x21, 0x44, 0x44, 0x44, 0x1e,
+        0x64, 0xf2, 0x72, 0xb9, 0x5c, 0x2e, 0x17, 0x82, 0x20, 0x08, 0x82, 0x20, 0x08, 0x82, 0x20, 0x84,
+        0x10, 0x42, 0x08, 0x21, 0x84, 0x10, 0x11, 0x11, 0x79, 0x01, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00,
+        0x00, 0x08, 0x00, 0x00, 0x00, 0x74, 0x68, 0x65, 0x20, 0x6c, 0x61, 0x7a, 0x79, 0x20, 0x64, 0x6f,
+        0x67, 0x6a, 0x75, 0x6d, 0x70, 0x73, 0x20, 0x6f, 0x76, 0x65, 0x72, 0x20, 0x74, 0x68, 0x65, 0x20,
+        0x71, 0x75, 0x69, 0x63, 0x6b, 0x20, 0x62, 0x72, 0x6f, 0x77, 0x6e, 0x20, 0x66, 0x6f, 0x78, 0x20
+    ];
+
+    #[test]
+    fn matches_a_dictionary_checked_with_zstd() {
+        let words: [&[u8]; 3] = [b"the quick brown fox ", b"jumps over ", b"the lazy dog"];
+        let mut tokens = Vec::new();
+        for round in 0..50u32 {
+            tokens.extend([256, 257, 258]);
+            tokens.extend((0..(round % 7)).map(|i| u32::from(b"abcdefg"[i as usize])));
+        }
+
+        let mut out = Vec::new();
+        write(&words, &[2, 1, 0], &tokens, 0, &mut out).unwrap();
+        assert_eq!(out, CHECKED);
+    }
+}